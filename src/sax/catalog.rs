@@ -0,0 +1,404 @@
+// Entity resolution with OASIS XML Catalogs 1.1
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use thiserror::Error;
+
+use crate::sax::{
+    parser::{error, Parser},
+    resolver::{resolve_uri, scheme, DenyResolver, EntityResolver},
+    Event,
+};
+
+const CATALOG_NAMESPACE: &str = "urn:oasis:names:tc:entity:xmlns:xml:catalog";
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("Catalog can't be read: {path} , {source}")]
+    Io { path: String, source: io::Error },
+
+    #[error("Catalog is not well-formed: {path} , {source}")]
+    Parsing {
+        path: String,
+        source: Box<error::Error>,
+    },
+}
+
+/// Resolves public and system identifiers with OASIS XML Catalogs.
+///
+/// `public`, `system`, `rewriteSystem`, `uri` and `nextCatalog` entries are supported,
+/// also in `group` elements, with `xml:base` and `prefer` attributes. Identifiers that
+/// are not in the catalogs go to the fallback resolver, [DenyResolver] by default.
+/// Catalog entries should point to local files, other URI schemes can't be opened.
+pub struct CatalogResolver {
+    catalog: Catalog,
+    fallback: Box<dyn EntityResolver>,
+}
+
+impl CatalogResolver {
+    /// Reads the catalog file and the catalogs of its `nextCatalog` entries.
+    /// Next catalogs that can't be read are ignored as the specification suggests.
+    pub fn from_file(path: &str) -> Result<CatalogResolver, CatalogError> {
+        let mut visited = HashSet::new();
+        Ok(CatalogResolver {
+            catalog: Catalog::read(path, &mut visited)?,
+            fallback: Box::new(DenyResolver),
+        })
+    }
+
+    pub fn with_fallback(mut self, fallback: impl EntityResolver + 'static) -> CatalogResolver {
+        self.fallback = Box::new(fallback);
+        self
+    }
+
+    /// URI of an external identifier from `system`, `rewriteSystem` and `public` entries.
+    /// `urn:publicid:` identifiers are matched as the public identifiers they wrap.
+    pub fn resolve_external_id(
+        &self,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> Option<String> {
+        let public_id = public_id
+            .map(|id| normalize_public_id(&unwrap_urn(id).unwrap_or_else(|| id.to_owned())));
+        // OASIS XML Catalogs §7.1.1, a different public identifier is kept
+        let (public_id, system_id) = match system_id.and_then(unwrap_urn) {
+            Some(unwrapped) => (public_id.or(Some(normalize_public_id(&unwrapped))), None),
+            None => (public_id, system_id),
+        };
+        self.catalog.external_id(public_id.as_deref(), system_id)
+    }
+
+    /// URI of a URI reference from `uri` entries.
+    pub fn resolve_uri(&self, uri: &str) -> Option<String> {
+        self.catalog.uri(uri)
+    }
+}
+
+impl EntityResolver for CatalogResolver {
+    fn resolve(
+        &self,
+        public_id: Option<&str>,
+        system_id: &str,
+        base_uri: Option<&str>,
+    ) -> io::Result<Option<Box<dyn Read>>> {
+        let uri = self
+            .resolve_external_id(public_id, Some(system_id))
+            .or_else(|| self.resolve_uri(system_id));
+        match uri {
+            Some(uri) => Ok(Some(Box::new(File::open(file_path(&uri)?)?))),
+            None => self.fallback.resolve(public_id, system_id, base_uri),
+        }
+    }
+}
+
+enum Entry {
+    Public {
+        public_id: String,
+        uri: String,
+        // `prefer="public"`, otherwise it is ignored when there is a system identifier
+        prefer_public: bool,
+    },
+    System {
+        system_id: String,
+        uri: String,
+    },
+    RewriteSystem {
+        start: String,
+        prefix: String,
+    },
+    Uri {
+        name: String,
+        uri: String,
+    },
+}
+
+struct Catalog {
+    entries: Vec<Entry>,
+    // `nextCatalog` entries in document order
+    next: Vec<Catalog>,
+}
+
+// attributes of `catalog` and `group` that apply to nested entries
+struct Scope {
+    base: String,
+    prefer_public: bool,
+}
+
+impl Catalog {
+    fn read(path: &str, visited: &mut HashSet<PathBuf>) -> Result<Catalog, CatalogError> {
+        let io_error = |source| CatalogError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let file_path = file_path(path).map_err(io_error)?;
+        let file = File::open(file_path).map_err(io_error)?;
+
+        let mut catalog = Catalog {
+            entries: Vec::new(),
+            next: Vec::new(),
+        };
+        // catalogs that are already read add nothing
+        let canonical = std::fs::canonicalize(file_path).map_err(io_error)?;
+        if !visited.insert(canonical) {
+            return Ok(catalog);
+        }
+
+        let mut p = Parser::from_reader(file);
+        let mut scopes = vec![Scope {
+            base: path.to_owned(),
+            prefer_public: true,
+        }];
+        let mut next_catalogs = Vec::new();
+        loop {
+            let event = p.read_event().map_err(|source| CatalogError::Parsing {
+                path: path.to_owned(),
+                source: Box::new(source),
+            })?;
+            let el = match event {
+                Event::StartElement(el) => el,
+                Event::EndElement(el) => {
+                    if el.namespace == CATALOG_NAMESPACE {
+                        scopes.pop();
+                    }
+                    continue;
+                }
+                Event::EndDocument => break,
+                _ => continue,
+            };
+            if el.namespace != CATALOG_NAMESPACE {
+                continue;
+            }
+
+            let scope = scopes.last().expect("catalog scope");
+            let attribute = |name: &str| {
+                el.attributes()
+                    .find(|attr| attr.name == name)
+                    .map(|attr| attr.value)
+            };
+            let base = match attribute("xml:base") {
+                Some(base) => resolve_uri(base, Some(&scope.base)),
+                None => scope.base.clone(),
+            };
+            let prefer_public = match attribute("prefer") {
+                Some(prefer) => prefer == "public",
+                None => scope.prefer_public,
+            };
+            let uri = |name: &str| attribute(name).map(|uri| resolve_uri(uri, Some(&base)));
+
+            match el.local_name {
+                "public" => {
+                    if let (Some(public_id), Some(uri)) = (attribute("publicId"), uri("uri")) {
+                        catalog.entries.push(Entry::Public {
+                            public_id: normalize_public_id(public_id),
+                            uri,
+                            prefer_public,
+                        });
+                    }
+                }
+                "system" => {
+                    if let (Some(system_id), Some(uri)) = (attribute("systemId"), uri("uri")) {
+                        catalog.entries.push(Entry::System {
+                            system_id: system_id.to_owned(),
+                            uri,
+                        });
+                    }
+                }
+                "rewriteSystem" => {
+                    if let (Some(start), Some(prefix)) =
+                        (attribute("systemIdStartString"), uri("rewritePrefix"))
+                    {
+                        catalog.entries.push(Entry::RewriteSystem {
+                            start: start.to_owned(),
+                            prefix,
+                        });
+                    }
+                }
+                "uri" => {
+                    if let (Some(name), Some(uri)) = (attribute("name"), uri("uri")) {
+                        catalog.entries.push(Entry::Uri {
+                            name: name.to_owned(),
+                            uri,
+                        });
+                    }
+                }
+                "nextCatalog" => next_catalogs.extend(uri("catalog")),
+                _ => {}
+            }
+
+            if !el.is_empty {
+                scopes.push(Scope {
+                    base,
+                    prefer_public,
+                });
+            }
+        }
+
+        for next in next_catalogs {
+            if let Ok(next) = Catalog::read(&next, visited) {
+                catalog.next.push(next);
+            }
+        }
+        Ok(catalog)
+    }
+
+    // OASIS XML Catalogs §7.1.2, the next catalogs are used if this one has no match
+    fn external_id(&self, public_id: Option<&str>, system_id: Option<&str>) -> Option<String> {
+        if let Some(system_id) = system_id {
+            let system = self.entries.iter().find_map(|entry| match entry {
+                Entry::System { system_id: id, uri } if id == system_id => Some(uri.clone()),
+                _ => None,
+            });
+            if system.is_some() {
+                return system;
+            }
+
+            // the longest start string wins
+            let rewrite = self
+                .entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    Entry::RewriteSystem { start, prefix }
+                        if system_id.starts_with(start.as_str()) =>
+                    {
+                        Some((start, prefix))
+                    }
+                    _ => None,
+                })
+                .max_by_key(|(start, _)| start.len());
+            if let Some((start, prefix)) = rewrite {
+                return Some(format!("{}{}", prefix, &system_id[start.len()..]));
+            }
+        }
+
+        if let Some(public_id) = public_id {
+            let public = self.entries.iter().find_map(|entry| match entry {
+                Entry::Public {
+                    public_id: id,
+                    uri,
+                    prefer_public,
+                } if id == public_id && (*prefer_public || system_id.is_none()) => {
+                    Some(uri.clone())
+                }
+                _ => None,
+            });
+            if public.is_some() {
+                return public;
+            }
+        }
+
+        self.next
+            .iter()
+            .find_map(|next| next.external_id(public_id, system_id))
+    }
+
+    fn uri(&self, name: &str) -> Option<String> {
+        self.entries
+            .iter()
+            .find_map(|entry| match entry {
+                Entry::Uri { name: n, uri } if n == name => Some(uri.clone()),
+                _ => None,
+            })
+            .or_else(|| self.next.iter().find_map(|next| next.uri(name)))
+    }
+}
+
+// public identifiers match after whitespace normalization, OASIS XML Catalogs §6.2
+fn normalize_public_id(public_id: &str) -> String {
+    public_id
+        .split([' ', '\t', '\r', '\n'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// public identifier of a `urn:publicid:` URN, OASIS XML Catalogs §6.4
+fn unwrap_urn(id: &str) -> Option<String> {
+    let prefix = id.get(..13)?;
+    if !prefix.eq_ignore_ascii_case("urn:publicid:") {
+        return None;
+    }
+    let mut public_id = String::with_capacity(id.len());
+    let mut rest = &id[13..];
+    while let Some(c) = rest.chars().next() {
+        let escaped = match rest.get(..3).map(|e| e.to_ascii_uppercase()).as_deref() {
+            Some("%2B") => Some('+'),
+            Some("%3A") => Some(':'),
+            Some("%2F") => Some('/'),
+            Some("%3B") => Some(';'),
+            Some("%27") => Some('\''),
+            Some("%3F") => Some('?'),
+            Some("%23") => Some('#'),
+            Some("%25") => Some('%'),
+            _ => None,
+        };
+        if let Some(escaped) = escaped {
+            public_id.push(escaped);
+            rest = &rest[3..];
+            continue;
+        }
+        match c {
+            '+' => public_id.push(' '),
+            ':' => public_id.push_str("//"),
+            ';' => public_id.push_str("::"),
+            c => public_id.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    Some(public_id)
+}
+
+// local path of a `file:` URI or a relative reference
+fn file_path(uri: &str) -> io::Result<&str> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(path);
+    }
+    if let Some(path) = uri.strip_prefix("file:") {
+        return Ok(path);
+    }
+    if scheme(uri).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("only local files can be opened: {}", uri),
+        ));
+    }
+    Ok(uri)
+}
+
+#[test]
+fn test_normalize_public_id() {
+    assert_eq!(
+        normalize_public_id("  -//OASIS//DTD\n DocBook XML  V4.5//EN "),
+        "-//OASIS//DTD DocBook XML V4.5//EN"
+    );
+}
+
+#[test]
+fn test_unwrap_urn() {
+    assert_eq!(
+        unwrap_urn("urn:publicid:-:OASIS:DTD+DocBook+XML+V4.1.2:EN").as_deref(),
+        Some("-//OASIS//DTD DocBook XML V4.1.2//EN")
+    );
+    assert_eq!(
+        unwrap_urn("URN:publicid:a%2Bb%3a;c%25").as_deref(),
+        Some("a+b:::c%")
+    );
+    assert_eq!(unwrap_urn("urn:isbn:0451450523"), None);
+    assert_eq!(unwrap_urn("dtd/a.dtd"), None);
+}
+
+#[test]
+fn test_file_path() {
+    assert_eq!(
+        file_path("file:///usr/share/a.dtd").unwrap(),
+        "/usr/share/a.dtd"
+    );
+    assert_eq!(file_path("dtd/a.dtd").unwrap(), "dtd/a.dtd");
+    assert!(file_path("http://example.com/a.dtd").is_err());
+    // a Windows drive, not a scheme
+    assert_eq!(file_path("C:/dtd/a.dtd").unwrap(), "C:/dtd/a.dtd");
+}
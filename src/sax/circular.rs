@@ -195,49 +195,21 @@ impl Buffer {
         Some(self.available_data())
     }
 
-    //FIXME: this should probably be rewritten, and tested extensively
-    #[doc(hidden)]
+    /// replaces `length` bytes of the available data at `start` with `data`
+    ///
+    /// returns `None` if the range is out of the available data or if the
+    /// result doesn't fit in the buffer, `shift()` or `grow()` can make room
     pub fn replace_slice(&mut self, data: &[u8], start: usize, length: usize) -> Option<usize> {
         let data_len = data.len();
-        if start + length > self.available_data()
-            || self.position + start + data_len > self.capacity
-        {
+        if start + length > self.available_data() || self.end - length + data_len > self.capacity {
             return None;
         }
 
-        unsafe {
-            let begin = self.position + start;
-            let slice_end = begin + data_len;
-            // we reduced the data size
-            if data_len < length {
-                ptr::copy(
-                    data.as_ptr(),
-                    (&mut self.memory[begin..slice_end]).as_mut_ptr(),
-                    data_len,
-                );
-
-                ptr::copy(
-                    (&self.memory[start + length..self.end]).as_ptr(),
-                    (&mut self.memory[slice_end..]).as_mut_ptr(),
-                    self.end - (start + length),
-                );
-                self.end = self.end - (length - data_len);
-
-            // we put more data in the buffer
-            } else {
-                ptr::copy(
-                    (&self.memory[start + length..self.end]).as_ptr(),
-                    (&mut self.memory[start + data_len..]).as_mut_ptr(),
-                    self.end - (start + length),
-                );
-                ptr::copy(
-                    data.as_ptr(),
-                    (&mut self.memory[begin..slice_end]).as_mut_ptr(),
-                    data_len,
-                );
-                self.end = self.end + data_len - length;
-            }
-        }
+        let begin = self.position + start;
+        self.memory
+            .copy_within(begin + length..self.end, begin + data_len);
+        self.memory[begin..begin + data_len].copy_from_slice(data);
+        self.end = self.end + data_len - length;
         Some(self.available_data())
     }
 
@@ -378,6 +350,25 @@ mod tests {
         assert_eq!(b.data(), &b"ab123Zgh"[..]);
     }
 
+    #[test]
+    fn replace_after_consume() {
+        let mut b = Buffer::with_capacity(10);
+        let _ = b.write(&b"abcdefgh"[..]);
+        b.consume(3);
+        assert_eq!(b.data(), &b"defgh"[..]);
+
+        assert_eq!(b.replace_slice(&b"XYZ"[..], 1, 1), Some(7));
+        assert_eq!(b.data(), &b"dXYZfgh"[..]);
+        assert_eq!(b.replace_slice(&b""[..], 0, 4), Some(3));
+        assert_eq!(b.data(), &b"fgh"[..]);
+
+        // 5 more bytes don't fit after the consumed ones until the data is shifted
+        assert_eq!(b.replace_slice(&b"123456"[..], 0, 1), None);
+        b.shift();
+        assert_eq!(b.replace_slice(&b"123456"[..], 0, 1), Some(8));
+        assert_eq!(b.data(), &b"123456gh"[..]);
+    }
+
     #[test]
     fn set_position() {
         let mut output = [0; 5];
@@ -0,0 +1,280 @@
+// Markup declarations of the internal and the external subset
+
+use std::collections::HashSet;
+
+use crate::sax::Attribute;
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum MarkupDecl {
+    Element(ElementDecl),
+    Attlist(AttlistDecl),
+    Entity(EntityDecl),
+    Notation(NotationDecl),
+}
+
+/// `<!ELEMENT name contentspec>`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ElementDecl {
+    pub name: String,
+    pub content_spec: ContentSpec,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ContentSpec {
+    Empty,
+    Any,
+    /// `(#PCDATA | a | b)*`, the element names in declaration order.
+    /// Empty for `(#PCDATA)`.
+    Mixed(Vec<String>),
+    Children(ContentParticle),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ContentParticle {
+    pub kind: ParticleKind,
+    pub repetition: Repetition,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParticleKind {
+    Name(String),
+    /// `(a | b)`
+    Choice(Vec<ContentParticle>),
+    /// `(a, b)`, also a single particle in parentheses
+    Seq(Vec<ContentParticle>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Repetition {
+    Once,
+    /// `?`
+    Optional,
+    /// `*`
+    ZeroOrMore,
+    /// `+`
+    OneOrMore,
+}
+
+/// `<!ATTLIST element ...>`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AttlistDecl {
+    pub element: String,
+    pub attributes: Vec<AttDef>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AttDef {
+    pub name: String,
+    pub att_type: AttType,
+    pub default: DefaultDecl,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AttType {
+    Cdata,
+    Id,
+    IdRef,
+    IdRefs,
+    Entity,
+    Entities,
+    NmToken,
+    NmTokens,
+    /// `NOTATION (a | b)`
+    Notation(Vec<String>),
+    /// `(a | b)`
+    Enumeration(Vec<String>),
+}
+
+/// Default values are as written between the quotes.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DefaultDecl {
+    Required,
+    Implied,
+    Fixed(String),
+    Value(String),
+}
+
+/// `<!ENTITY name ...>` or `<!ENTITY % name ...>`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EntityDecl {
+    pub name: String,
+    /// `true` for a parameter entity
+    pub parameter: bool,
+    pub definition: EntityDef,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EntityDef {
+    /// The literal entity value as written between the quotes
+    Internal(String),
+    External {
+        external_id: ExternalId,
+        /// Notation of an unparsed entity, `NDATA name`
+        notation: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ExternalId {
+    pub public_id: Option<String>,
+    pub system_id: String,
+}
+
+/// `<!NOTATION name ...>`, at least one of the identifiers is set.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NotationDecl {
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+}
+
+/// An unparsed entity, `<!ENTITY name SYSTEM "uri" NDATA notation>`, XML 1.0 §4.7
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct UnparsedEntity<'a> {
+    pub name: &'a str,
+    pub public_id: Option<&'a str>,
+    pub system_id: &'a str,
+    /// Name of the notation
+    pub notation: &'a str,
+    /// `None` if the notation is not declared
+    pub notation_decl: Option<&'a NotationDecl>,
+}
+
+/// The DTD of a document: declarations of the internal subset followed by the ones
+/// of the external subset, with parameter entities expanded.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Dtd {
+    /// Name of the root element
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub declarations: Vec<MarkupDecl>,
+}
+
+impl Dtd {
+    pub fn element(&self, name: &str) -> Option<&ElementDecl> {
+        self.declarations.iter().find_map(|decl| match decl {
+            MarkupDecl::Element(el) if el.name == name => Some(el),
+            _ => None,
+        })
+    }
+
+    /// Attribute definitions of an element from all of its `ATTLIST` declarations.
+    /// The first definition of an attribute is binding, later ones are skipped.
+    pub fn attributes(&self, element: &str) -> Vec<&AttDef> {
+        let mut attributes: Vec<&AttDef> = Vec::new();
+        for decl in &self.declarations {
+            let MarkupDecl::Attlist(list) = decl else {
+                continue;
+            };
+            if list.element != element {
+                continue;
+            }
+            for def in &list.attributes {
+                if !attributes.iter().any(|a| a.name == def.name) {
+                    attributes.push(def);
+                }
+            }
+        }
+        attributes
+    }
+
+    /// The binding declaration of a general entity.
+    pub fn entity(&self, name: &str) -> Option<&EntityDecl> {
+        self.find_entity(name, false)
+    }
+
+    /// The binding declaration of a parameter entity.
+    pub fn parameter_entity(&self, name: &str) -> Option<&EntityDecl> {
+        self.find_entity(name, true)
+    }
+
+    pub fn notation(&self, name: &str) -> Option<&NotationDecl> {
+        self.declarations.iter().find_map(|decl| match decl {
+            MarkupDecl::Notation(notation) if notation.name == name => Some(notation),
+            _ => None,
+        })
+    }
+
+    /// Notations in declaration order. A notation name is declared only once in a
+    /// valid DTD, later declarations are skipped.
+    pub fn notations(&self) -> Vec<&NotationDecl> {
+        let mut names = HashSet::new();
+        self.declarations
+            .iter()
+            .filter_map(|decl| match decl {
+                MarkupDecl::Notation(notation) if names.insert(&notation.name) => Some(notation),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Unparsed entities in declaration order, with binding declarations only.
+    pub fn unparsed_entities(&self) -> Vec<UnparsedEntity<'_>> {
+        let mut names = HashSet::new();
+        self.declarations
+            .iter()
+            .filter_map(|decl| match decl {
+                MarkupDecl::Entity(entity) if !entity.parameter && names.insert(&entity.name) => {
+                    self.unparsed(entity)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn unparsed_entity(&self, name: &str) -> Option<UnparsedEntity<'_>> {
+        self.entity(name).and_then(|entity| self.unparsed(entity))
+    }
+
+    /// Unparsed entities named by an `ENTITY` or `ENTITIES` attribute of the element.
+    /// `None` if the attribute is not declared with one of these types. Names that are
+    /// not unparsed entities are left out, they break a validity constraint.
+    pub fn attribute_entities(
+        &self,
+        element: &str,
+        attribute: &Attribute,
+    ) -> Option<Vec<UnparsedEntity<'_>>> {
+        let def = self
+            .attributes(element)
+            .into_iter()
+            .find(|def| def.name == attribute.name)?;
+        let names = match def.att_type {
+            AttType::Entity => vec![attribute.value],
+            AttType::Entities => attribute.value.split(' ').collect(),
+            _ => return None,
+        };
+        Some(
+            names
+                .into_iter()
+                .filter_map(|name| self.unparsed_entity(name))
+                .collect(),
+        )
+    }
+
+    fn unparsed<'a>(&'a self, entity: &'a EntityDecl) -> Option<UnparsedEntity<'a>> {
+        let EntityDef::External {
+            external_id,
+            notation: Some(notation),
+        } = &entity.definition
+        else {
+            return None;
+        };
+        Some(UnparsedEntity {
+            name: &entity.name,
+            public_id: external_id.public_id.as_deref(),
+            system_id: &external_id.system_id,
+            notation,
+            notation_decl: self.notation(notation),
+        })
+    }
+
+    fn find_entity(&self, name: &str, parameter: bool) -> Option<&EntityDecl> {
+        self.declarations.iter().find_map(|decl| match decl {
+            MarkupDecl::Entity(entity) if entity.name == name && entity.parameter == parameter => {
+                Some(entity)
+            }
+            _ => None,
+        })
+    }
+}
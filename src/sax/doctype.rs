@@ -0,0 +1,1167 @@
+// Entity expansion, reading of the DTD subsets and attribute defaults
+
+use std::{cell::Cell, collections::HashMap, io::Read, ops::Range};
+
+use crate::{
+    sax as xml_sax,
+    sax::declaration::{
+        AttDef, AttType, AttlistDecl, DefaultDecl, Dtd, EntityDecl, EntityDef, ExternalId,
+        MarkupDecl,
+    },
+    sax::dtd::markupdecl_29_values,
+    sax::encoding::Decoder,
+    sax::internal::{name, AttributeRange},
+    sax::parser::{error, options, push_str_get_range, syntax_error},
+    sax::resolver::{resolve_uri, DenyResolver, EntityResolver},
+    sax::validation::ValidityErrorKind,
+};
+
+// character references and predefined entities
+pub(crate) fn resolve_reference(raw: &str) -> Option<char> {
+    if raw.starts_with("&#x") {
+        let hex_val = &raw[3..raw.len() - 1];
+
+        match u32::from_str_radix(hex_val, 16) {
+            Ok(a) => char::from_u32(a),
+            Err(_) => None,
+        }
+    } else if raw.starts_with("&#") {
+        let dec_val = &raw[2..raw.len() - 1];
+
+        match dec_val.parse::<u32>() {
+            Ok(a) => char::from_u32(a),
+            Err(_) => None,
+        }
+    } else {
+        match raw {
+            "&amp;" => Some('&'),
+            "&lt;" => Some('<'),
+            "&gt;" => Some('>'),
+            "&quot;" => Some('"'),
+            "&apos;" => Some('\''),
+            _ => None,
+        }
+    }
+}
+
+// the amplification limit doesn't apply to small documents with many references
+const AMPLIFICATION_THRESHOLD: usize = 1024 * 1024;
+
+// general entities declared in the DTD
+pub(crate) struct Entities {
+    general: HashMap<String, GeneralEntity>,
+    // Entity Declared WFC, otherwise undeclared references are reported as they are
+    must_be_declared: bool,
+
+    pub(crate) limits: options::EntityLimits,
+    // bytes of replacement text so far, counted through shared references
+    expanded_size: Cell<usize>,
+    // bytes read from the reader so far
+    pub(crate) input_size: usize,
+
+    pub(crate) resolver: Box<dyn EntityResolver>,
+    // base of relative system identifiers in the internal subset
+    pub(crate) base_uri: Option<String>,
+}
+
+enum GeneralEntity {
+    // replacement text, XML 1.0 §4.5
+    Internal(String),
+    External(ExternalEntity),
+    Unparsed,
+}
+
+// external parsed entity, loaded on the first reference in content
+struct ExternalEntity {
+    external_id: ExternalId,
+    // URI of the declaring document or external subset
+    base_uri: Option<String>,
+    loaded: bool,
+    // `None` if the resolver doesn't resolve it
+    replacement: Option<String>,
+}
+
+impl Entities {
+    pub(crate) fn new() -> Entities {
+        Entities {
+            general: HashMap::new(),
+            must_be_declared: true,
+            limits: options::EntityLimits::default(),
+            expanded_size: Cell::new(0),
+            input_size: 0,
+            resolver: Box::new(DenyResolver),
+            base_uri: None,
+        }
+    }
+
+    // counts an expansion against the limits, `depth` is 1 for a reference in the document
+    fn check_limits(&self, name: &str, depth: usize, size: usize) -> Result<(), error::ErrorKind> {
+        let exceeded = |limit| {
+            Err(error::ErrorKind::EntityExpansionLimit {
+                name: name.to_owned(),
+                limit,
+            })
+        };
+        if depth > self.limits.max_depth {
+            return exceeded("nesting depth");
+        }
+        let expanded_size = self.expanded_size.get().saturating_add(size);
+        self.expanded_size.set(expanded_size);
+        if expanded_size > self.limits.max_expanded_size {
+            return exceeded("expanded size");
+        }
+        if expanded_size > AMPLIFICATION_THRESHOLD
+            && expanded_size / self.limits.max_amplification.max(1) > self.input_size
+        {
+            return exceeded("amplification");
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, declarations: &[MarkupDecl], base_uri: Option<&str>) {
+        for decl in declarations {
+            let MarkupDecl::Entity(EntityDecl {
+                name,
+                parameter: false,
+                definition,
+            }) = decl
+            else {
+                continue;
+            };
+            // the first declaration is binding
+            self.general
+                .entry(name.clone())
+                .or_insert_with(|| match definition {
+                    EntityDef::Internal(value) => GeneralEntity::Internal(replacement_text(value)),
+                    EntityDef::External {
+                        external_id,
+                        notation: None,
+                    } => GeneralEntity::External(ExternalEntity {
+                        external_id: external_id.clone(),
+                        base_uri: base_uri.map(|uri| uri.to_owned()),
+                        loaded: false,
+                        replacement: None,
+                    }),
+                    EntityDef::External {
+                        notation: Some(_), ..
+                    } => GeneralEntity::Unparsed,
+                });
+        }
+    }
+
+    // replacement text of an internal or a loaded external entity
+    pub(crate) fn replacement(&self, name: &str) -> Option<&str> {
+        match self.general.get(name)? {
+            GeneralEntity::Internal(text) => Some(text),
+            GeneralEntity::External(entity) => entity.replacement.as_deref(),
+            GeneralEntity::Unparsed => None,
+        }
+    }
+
+    // whether a reference in content is expanded, unresolved external entities are not
+    pub(crate) fn expands_in_content(
+        &mut self,
+        name: &str,
+        frames: &[EntityFrame],
+    ) -> Result<bool, error::ErrorKind> {
+        if let Some(GeneralEntity::External(entity)) = self.general.get(name) {
+            if !entity.loaded {
+                let id = &entity.external_id;
+                let replacement = self.load(
+                    id.public_id.as_deref(),
+                    &id.system_id,
+                    entity.base_uri.as_deref(),
+                )?;
+                if let Some(GeneralEntity::External(entity)) = self.general.get_mut(name) {
+                    entity.loaded = true;
+                    entity.replacement = replacement;
+                }
+            }
+        }
+
+        match self.general.get(name) {
+            Some(GeneralEntity::Internal(_) | GeneralEntity::External(_)) => {
+                let Some(text) = self.replacement(name) else {
+                    return Ok(false);
+                };
+                // No Recursion WFC
+                if frames.iter().any(|frame| frame.name == name) {
+                    return Err(error::ErrorKind::RecursiveEntity {
+                        name: name.to_owned(),
+                    });
+                }
+                self.check_limits(name, frames.len() + 1, text.len())?;
+                Ok(true)
+            }
+            Some(GeneralEntity::Unparsed) => Err(error::ErrorKind::UnparsedEntityReference {
+                name: name.to_owned(),
+            }),
+            None if self.must_be_declared => Err(error::ErrorKind::UndeclaredEntity {
+                name: name.to_owned(),
+            }),
+            None => Ok(false),
+        }
+    }
+
+    // reads an external entity with the resolver, the text declaration is dropped
+    fn load(
+        &self,
+        public_id: Option<&str>,
+        system_id: &str,
+        base_uri: Option<&str>,
+    ) -> Result<Option<String>, error::ErrorKind> {
+        let failed = |reason: String| error::ErrorKind::ExternalEntity {
+            system_id: system_id.to_owned(),
+            reason,
+        };
+        let Some(reader) = self
+            .resolver
+            .resolve(public_id, system_id, base_uri)
+            .map_err(|err| failed(err.to_string()))?
+        else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::new();
+        let max_size = self.limits.max_expanded_size;
+        reader
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|err| failed(err.to_string()))?;
+        if data.len() > max_size {
+            return Err(error::ErrorKind::EntityExpansionLimit {
+                name: system_id.to_owned(),
+                limit: "expanded size",
+            });
+        }
+        let mut decoder = Decoder::new();
+        let mut decoded = decoder
+            .decode(&data, true)
+            .map_err(|err| failed(err.kind.to_string()))?
+            .to_vec();
+        if let Some(encoding) = text_declaration_encoding(&decoded) {
+            // the declaration is ASCII, the rest is decoded again
+            let redecode = decoder
+                .declared(encoding)
+                .map_err(|kind| failed(kind.to_string()))?;
+            if redecode {
+                decoder.rewind(data.len());
+                decoded = decoder
+                    .decode(&data, true)
+                    .map_err(|err| failed(err.kind.to_string()))?
+                    .to_vec();
+            }
+        }
+        let data = decoded;
+        let text = String::from_utf8(data).map_err(|err| failed(err.to_string()))?;
+        Ok(Some(strip_text_declaration(&text).to_owned()))
+    }
+}
+
+// the encoding name of the text declaration
+fn text_declaration_encoding(text: &[u8]) -> Option<&str> {
+    let text = text.strip_prefix("\u{FEFF}".as_bytes()).unwrap_or(text);
+    let declaration = &text[..text.windows(2).position(|w| w == b"?>")?];
+    if !declaration.starts_with(b"<?xml") {
+        return None;
+    }
+    let declaration = std::str::from_utf8(declaration).ok()?;
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    rest[1..].split(quote).next()
+}
+
+// the byte order mark and `<?xml encoding="UTF-8"?>` at the start of an external entity
+fn strip_text_declaration(text: &str) -> &str {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let is_declaration =
+        text.starts_with("<?xml") && text[5..].starts_with([' ', '\t', '\r', '\n']);
+    match text.find("?>") {
+        Some(end) if is_declaration => &text[end + 2..],
+        _ => text,
+    }
+}
+
+#[test]
+fn test_text_declaration_encoding() {
+    assert_eq!(
+        text_declaration_encoding(b"<?xml version='1.0' encoding = \"ISO-8859-1\"?>text"),
+        Some("ISO-8859-1")
+    );
+    assert_eq!(text_declaration_encoding(b"<?xml version='1.0'?>"), None);
+    assert_eq!(text_declaration_encoding(b"text"), None);
+}
+
+#[test]
+fn test_strip_text_declaration() {
+    assert_eq!(strip_text_declaration("text"), "text");
+    assert_eq!(
+        strip_text_declaration("\u{FEFF}<?xml encoding='UTF-8'?><a/>"),
+        "<a/>"
+    );
+    assert_eq!(
+        strip_text_declaration("<?xml-stylesheet?>"),
+        "<?xml-stylesheet?>"
+    );
+}
+
+// character references of a literal entity value are resolved, entity references are kept
+fn replacement_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("&#") {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(';').map_or(rest.len(), |pos| pos + 1);
+        match resolve_reference(&rest[..end]) {
+            Some(c) => text.push(c),
+            None => text.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    text.push_str(rest);
+    text
+}
+
+#[test]
+fn test_replacement_text() {
+    assert_eq!(
+        replacement_text("An ampersand (&#38;#38;) &#x3C;p> &e;"),
+        "An ampersand (&#38;) <p> &e;"
+    );
+}
+
+// replacement text of an entity in the buffer, its bytes don't move the cursor
+pub(crate) struct EntityFrame {
+    pub(crate) name: String,
+    // distance from the start of the buffer data to the end of the replacement text
+    pub(crate) end: usize,
+    pub(crate) element_level: usize,
+}
+
+// appends the normalized replacement text of an entity referenced in an attribute value,
+// returns false when the reference is kept as it is
+fn push_attribute_entity<'e>(
+    strbuffer: &mut String,
+    name: &str,
+    entities: &'e Entities,
+    open: &mut Vec<&'e str>,
+) -> Result<bool, error::ErrorKind> {
+    let (name, text) = match entities.general.get_key_value(name) {
+        Some((name, GeneralEntity::Internal(text))) => (name.as_str(), text),
+        Some((name, GeneralEntity::External(_))) => {
+            return Err(error::ErrorKind::ExternalEntityInAttribute { name: name.clone() })
+        }
+        Some((name, GeneralEntity::Unparsed)) => {
+            return Err(error::ErrorKind::UnparsedEntityReference { name: name.clone() })
+        }
+        None if entities.must_be_declared => {
+            return Err(error::ErrorKind::UndeclaredEntity {
+                name: name.to_owned(),
+            })
+        }
+        None => return Ok(false),
+    };
+    if open.contains(&name) {
+        return Err(error::ErrorKind::RecursiveEntity {
+            name: name.to_owned(),
+        });
+    }
+    if text.contains('<') {
+        return Err(error::ErrorKind::LessThanInAttributeValue {
+            name: name.to_owned(),
+        });
+    }
+    entities.check_limits(name, open.len() + 1, text.len())?;
+
+    open.push(name);
+    let mut rest = text.as_str();
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '&' => {
+                let end = rest.find(';').map_or(rest.len(), |pos| pos + 1);
+                let reference = &rest[..end];
+                match resolve_reference(reference) {
+                    Some(resolved) => strbuffer.push(resolved),
+                    None => {
+                        let name = &reference[1..reference.len() - 1];
+                        if !push_attribute_entity(strbuffer, name, entities, open)? {
+                            strbuffer.push_str(reference);
+                        }
+                    }
+                }
+                rest = &rest[end..];
+                continue;
+            }
+            '\t' | '\n' | '\r' => strbuffer.push(' '),
+            c => strbuffer.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    open.pop();
+    Ok(true)
+}
+
+// Attribute-Value Normalization, XML 1.0 §3.3.3
+// References are resolved and whitespace characters are replaced with spaces.
+// The raw range is returned as is when there is nothing to normalize.
+pub(crate) fn normalize_attribute_value(
+    strbuffer: &mut String,
+    raw_range: Range<usize>,
+    entities: &Entities,
+) -> Result<Range<usize>, error::ErrorKind> {
+    let raw = &strbuffer.as_bytes()[raw_range.clone()];
+    if !raw
+        .iter()
+        .any(|b| matches!(b, b'&' | b'\t' | b'\n' | b'\r'))
+    {
+        return Ok(raw_range);
+    }
+
+    let start = strbuffer.len();
+    let mut i = raw_range.start;
+    while let Some(c) = strbuffer[i..raw_range.end].chars().next() {
+        match c {
+            '&' => {
+                // AttValue guarantees the reference is terminated
+                let end = match strbuffer[i..raw_range.end].find(';') {
+                    Some(pos) => i + pos + 1,
+                    None => raw_range.end,
+                };
+                match resolve_reference(&strbuffer[i..end]) {
+                    Some(resolved) => strbuffer.push(resolved),
+                    None => {
+                        let name = strbuffer[i + 1..end - 1].to_owned();
+                        if !push_attribute_entity(strbuffer, &name, entities, &mut Vec::new())? {
+                            push_within(strbuffer, i..end);
+                        }
+                    }
+                }
+                i = end;
+            }
+            '\r' => {
+                strbuffer.push(' ');
+                i += 1;
+                if strbuffer.as_bytes()[i..raw_range.end].starts_with(b"\n") {
+                    i += 1;
+                }
+            }
+            '\t' | '\n' => {
+                strbuffer.push(' ');
+                i += 1;
+            }
+            c => {
+                strbuffer.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    Ok(start..strbuffer.len())
+}
+
+// copies a range of the buffer to its end
+fn push_within(strbuffer: &mut String, range: Range<usize>) {
+    let mut i = range.start;
+    while let Some(c) = strbuffer[i..range.end].chars().next() {
+        strbuffer.push(c);
+        i += c.len_utf8();
+    }
+}
+
+#[test]
+fn test_normalize_attribute_value() {
+    let mut entities = Entities::new();
+    entities.must_be_declared = false;
+
+    let mut strbuffer = String::from("plain");
+    let range = normalize_attribute_value(&mut strbuffer, 0..5, &entities).unwrap();
+    assert_eq!(range, 0..5);
+    assert_eq!(strbuffer.len(), 5);
+
+    let mut strbuffer = String::from("a\tb\r\nc\nd&amp;&#10;&#x20AC;&custom;ğ");
+    let raw_range = 0..strbuffer.len();
+    let range = normalize_attribute_value(&mut strbuffer, raw_range, &entities).unwrap();
+    assert_eq!(&strbuffer[range], "a b c d&\n€&custom;ğ");
+
+    let declarations = subset_declarations(
+        r#"<!ENTITY a "x&#10;&b;"><!ENTITY b "&#38;#38;&amp;"><!ENTITY c "&c;">"#,
+        false,
+    )
+    .unwrap();
+    entities.declare(&declarations, None);
+    let mut strbuffer = String::from("&a;&undeclared;");
+    let raw_range = 0..strbuffer.len();
+    let range = normalize_attribute_value(&mut strbuffer, raw_range, &entities).unwrap();
+    assert_eq!(&strbuffer[range], "x &&&undeclared;");
+
+    let mut strbuffer = String::from("&c;");
+    assert_eq!(
+        normalize_attribute_value(&mut strbuffer, 0..3, &entities),
+        Err(error::ErrorKind::RecursiveEntity {
+            name: "c".to_owned()
+        })
+    );
+}
+
+// attribute list declarations of the internal subset by element name
+#[derive(Default)]
+pub(crate) struct AttributeDefaults {
+    pub(crate) elements: HashMap<String, Vec<AttDef>>,
+}
+
+impl AttributeDefaults {
+    fn declare(&mut self, declarations: &[MarkupDecl]) {
+        for decl in declarations {
+            let MarkupDecl::Attlist(AttlistDecl {
+                element,
+                attributes,
+            }) = decl
+            else {
+                continue;
+            };
+            let definitions = self.elements.entry(element.clone()).or_default();
+            for def in attributes {
+                // the first definition of an attribute is binding
+                if !definitions.iter().any(|d| d.name == def.name) {
+                    definitions.push(def.clone());
+                }
+            }
+        }
+    }
+}
+
+// normalizes declared attributes by their type, adds the missing ones with a default value
+// and returns the specified values that differ from #FIXED ones, a validity error
+pub(crate) fn add_default_attributes(
+    strbuffer: &mut String,
+    attribute_list: &mut Vec<AttributeRange>,
+    element: &str,
+    definitions: &[AttDef],
+    entities: &Entities,
+) -> Result<Vec<ValidityErrorKind>, error::ErrorKind> {
+    let mut mismatches = Vec::new();
+    for def in definitions {
+        let specified = attribute_list
+            .iter()
+            .position(|attr| strbuffer[attr.name.clone()] == def.name);
+
+        if let Some(i) = specified {
+            if def.att_type != AttType::Cdata {
+                attribute_list[i].value =
+                    collapse_spaces(strbuffer, attribute_list[i].value.clone());
+            }
+        }
+
+        let (DefaultDecl::Fixed(literal) | DefaultDecl::Value(literal)) = &def.default else {
+            continue;
+        };
+        let raw_value = push_str_get_range(strbuffer, literal);
+        let mut value = normalize_attribute_value(strbuffer, raw_value.clone(), entities)?;
+        if def.att_type != AttType::Cdata {
+            value = collapse_spaces(strbuffer, value);
+        }
+
+        match specified {
+            Some(i) => {
+                let found = &strbuffer[attribute_list[i].value.clone()];
+                if matches!(def.default, DefaultDecl::Fixed(_))
+                    && found != &strbuffer[value.clone()]
+                {
+                    mismatches.push(ValidityErrorKind::FixedAttributeMismatch {
+                        element: element.to_owned(),
+                        name: def.name.clone(),
+                        expected: strbuffer[value].to_owned(),
+                        found: found.to_owned(),
+                    });
+                }
+                strbuffer.truncate(raw_value.start);
+            }
+            None => {
+                let name = push_str_get_range(strbuffer, &def.name);
+                attribute_list.push(AttributeRange {
+                    value,
+                    raw_value,
+                    name,
+                    local_name: 0..0,
+                    prefix: 0..0,
+                    namespace: 0..0,
+                    specified: false,
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+// non-CDATA values drop leading and trailing spaces and keep single spaces between tokens
+fn collapse_spaces(strbuffer: &mut String, range: Range<usize>) -> Range<usize> {
+    let value = &strbuffer[range.clone()];
+    if !value.starts_with(' ') && !value.ends_with(' ') && !value.contains("  ") {
+        return range;
+    }
+    let collapsed = value
+        .split(' ')
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    push_str_get_range(strbuffer, &collapsed)
+}
+
+#[test]
+fn test_add_default_attributes() {
+    let declarations = subset_declarations(
+        r#"<!ATTLIST e a CDATA " x " b NMTOKENS "  1  2 " c CDATA #FIXED "f" d ID #IMPLIED><!ATTLIST e a CDATA "ignored">"#,
+        false,
+    )
+    .unwrap();
+    let mut defaults = AttributeDefaults::default();
+    defaults.declare(&declarations);
+    assert_eq!(defaults.elements["e"].len(), 4);
+
+    let entities = Entities::new();
+    let mut strbuffer = String::from("d  id ");
+    let mut attribute_list = vec![AttributeRange {
+        value: 1..6,
+        raw_value: 1..6,
+        name: 0..1,
+        local_name: 0..0,
+        prefix: 0..0,
+        namespace: 0..0,
+        specified: true,
+    }];
+    let mismatches = add_default_attributes(
+        &mut strbuffer,
+        &mut attribute_list,
+        "e",
+        &defaults.elements["e"],
+        &entities,
+    )
+    .unwrap();
+    assert!(mismatches.is_empty());
+    let attributes: Vec<_> = attribute_list
+        .iter()
+        .map(|attr| {
+            (
+                &strbuffer[attr.name.clone()],
+                &strbuffer[attr.value.clone()],
+                attr.specified,
+            )
+        })
+        .collect();
+    assert_eq!(
+        attributes,
+        vec![
+            ("d", "id", true),
+            ("a", " x ", false),
+            ("b", "1 2", false),
+            ("c", "f", false)
+        ]
+    );
+
+    let mut strbuffer = String::from("cg");
+    let mut attribute_list = vec![AttributeRange {
+        value: 1..2,
+        raw_value: 1..2,
+        name: 0..1,
+        local_name: 0..0,
+        prefix: 0..0,
+        namespace: 0..0,
+        specified: true,
+    }];
+    assert_eq!(
+        add_default_attributes(
+            &mut strbuffer,
+            &mut attribute_list,
+            "e",
+            &defaults.elements["e"],
+            &entities,
+        ),
+        Ok(vec![ValidityErrorKind::FixedAttributeMismatch {
+            element: "e".to_owned(),
+            name: "c".to_owned(),
+            expected: "f".to_owned(),
+            found: "g".to_owned(),
+        }])
+    );
+}
+
+// parameter entity declared in the DTD
+struct ParameterEntity {
+    definition: EntityDef,
+    // URI of the declaring document or entity
+    base_uri: Option<String>,
+}
+
+// reads the internal subset, then the external subset, parameter entities are expanded
+struct SubsetReader<'p> {
+    entities: &'p mut Entities,
+    attribute_defaults: &'p mut AttributeDefaults,
+    parameter: HashMap<String, ParameterEntity>,
+    // parameter entities being expanded, innermost last
+    open: Vec<String>,
+    declarations: Vec<MarkupDecl>,
+    standalone: bool,
+    // XML 1.0 §5.1, entity and attribute list declarations after a parameter entity
+    // reference that isn't read are not processed
+    skipped_reference: bool,
+    has_pe_references: bool,
+}
+
+impl<'p> SubsetReader<'p> {
+    fn new(
+        entities: &'p mut Entities,
+        attribute_defaults: &'p mut AttributeDefaults,
+        standalone: bool,
+    ) -> SubsetReader<'p> {
+        SubsetReader {
+            entities,
+            attribute_defaults,
+            parameter: HashMap::new(),
+            open: Vec::new(),
+            declarations: Vec::new(),
+            standalone,
+            skipped_reference: false,
+            has_pe_references: false,
+        }
+    }
+
+    // `extSubsetDecl` if `external`, otherwise `intSubset` where parameter entity references
+    // are only allowed between declarations
+    fn read(
+        &mut self,
+        mut text: &str,
+        external: bool,
+        base_uri: Option<&str>,
+    ) -> Result<(), error::ErrorKind> {
+        let mut include_depth = 0;
+        loop {
+            text = text.trim_start_matches(XML_WHITESPACE);
+            if text.is_empty() {
+                break;
+            }
+
+            if let Some((name, rest)) = parameter_reference(text) {
+                self.has_pe_references = true;
+                if let Some((replacement, base_uri)) = self.parameter_text(name)? {
+                    self.open.push(name.to_owned());
+                    self.read(&replacement, true, base_uri.as_deref())?;
+                    self.open.pop();
+                }
+                text = rest;
+            } else if external && text.starts_with("<![") {
+                let (include, rest) = self.conditional_section(&text[3..])?;
+                if include {
+                    include_depth += 1;
+                    text = rest;
+                } else {
+                    text = ignore_section(rest)?;
+                }
+            } else if include_depth > 0 && text.starts_with("]]>") {
+                include_depth -= 1;
+                text = &text[3..];
+            } else {
+                let end = declaration_end(text)
+                    .ok_or_else(|| syntax_error("markup declaration", text.as_bytes()))?;
+                if external {
+                    let mut declaration = String::with_capacity(end);
+                    let is_entity = text.starts_with("<!ENTITY");
+                    if self.expand_in_declaration(&text[..end], &mut declaration, is_entity)? {
+                        self.declare(&declaration, base_uri)?;
+                    }
+                } else {
+                    self.declare(&text[..end], base_uri)?;
+                }
+                text = &text[end..];
+            }
+        }
+        if include_depth > 0 {
+            return Err(syntax_error("]]>", text.as_bytes()));
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, text: &str, base_uri: Option<&str>) -> Result<(), error::ErrorKind> {
+        let decl = match markupdecl_29_values(text.as_bytes()) {
+            Ok(([], decl)) => decl,
+            _ => return Err(syntax_error("markup declaration", text.as_bytes())),
+        };
+        let Some(decl) = decl else {
+            return Ok(());
+        };
+        match &decl {
+            // not in the DTD either, it only has the processed declarations
+            MarkupDecl::Entity(_) | MarkupDecl::Attlist(_) if self.skipped_reference => {
+                return Ok(());
+            }
+            MarkupDecl::Entity(EntityDecl {
+                name,
+                parameter: true,
+                definition,
+            }) => {
+                // the first declaration is binding
+                self.parameter
+                    .entry(name.clone())
+                    .or_insert_with(|| ParameterEntity {
+                        definition: definition.clone(),
+                        base_uri: base_uri.map(|uri| uri.to_owned()),
+                    });
+            }
+            MarkupDecl::Entity(_) => {
+                self.entities.declare(std::slice::from_ref(&decl), base_uri);
+            }
+            MarkupDecl::Attlist(_) => {
+                self.attribute_defaults.declare(std::slice::from_ref(&decl));
+            }
+            _ => {}
+        }
+        self.declarations.push(decl);
+        Ok(())
+    }
+
+    // replacement text of a parameter entity and the base URI of its declarations,
+    // `None` if it isn't read
+    fn parameter_text(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<(String, Option<String>)>, error::ErrorKind> {
+        let reference = format!("%{}", name);
+        // No Recursion WFC
+        if self.open.iter().any(|open| open == name) {
+            return Err(error::ErrorKind::RecursiveEntity { name: reference });
+        }
+        let text = match self.parameter.get(name) {
+            Some(ParameterEntity {
+                definition: EntityDef::Internal(value),
+                base_uri,
+            }) => Some((replacement_text(value), base_uri.clone())),
+            Some(ParameterEntity {
+                definition: EntityDef::External { external_id, .. },
+                base_uri,
+            }) => {
+                let system_id = &external_id.system_id;
+                self.entities
+                    .load(
+                        external_id.public_id.as_deref(),
+                        system_id,
+                        base_uri.as_deref(),
+                    )?
+                    .map(|text| (text, Some(resolve_uri(system_id, base_uri.as_deref()))))
+            }
+            // Entity Declared WFC
+            None if self.standalone => {
+                return Err(error::ErrorKind::UndeclaredEntity { name: reference });
+            }
+            None => None,
+        };
+        match &text {
+            Some((text, _)) => {
+                self.entities
+                    .check_limits(&reference, self.open.len() + 1, text.len())?;
+            }
+            None => self.skipped_reference = true,
+        }
+        Ok(text)
+    }
+
+    // copies a declaration of an external subset or entity with its parameter entity
+    // references expanded, `false` if one of them isn't read
+    fn expand_in_declaration(
+        &mut self,
+        text: &str,
+        declaration: &mut String,
+        is_entity: bool,
+    ) -> Result<bool, error::ErrorKind> {
+        let mut quote = None;
+        // a literal entity value, system and public literals are not expanded
+        let mut in_entity_value = false;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if c == '%' && (quote.is_none() || in_entity_value) {
+                if let Some((name, after)) = parameter_reference(rest) {
+                    let Some((replacement, _)) = self.parameter_text(name)? else {
+                        return Ok(false);
+                    };
+                    self.open.push(name.to_owned());
+                    let expanded = if quote.is_none() {
+                        // XML 1.0 §4.4.8, included as PE
+                        declaration.push(' ');
+                        let expanded =
+                            self.expand_in_declaration(&replacement, declaration, is_entity)?;
+                        declaration.push(' ');
+                        expanded
+                    } else {
+                        // §4.4.5, included in literal
+                        self.expand_in_literal(&replacement, declaration)?
+                    };
+                    self.open.pop();
+                    if !expanded {
+                        return Ok(false);
+                    }
+                    rest = after;
+                    continue;
+                }
+            }
+
+            match quote {
+                None if c == '"' || c == '\'' => {
+                    let previous = declaration.trim_end();
+                    in_entity_value = is_entity
+                        && !(previous.ends_with("SYSTEM")
+                            || previous.ends_with("PUBLIC")
+                            || previous.ends_with(['"', '\'']));
+                    quote = Some(c);
+                }
+                Some(q) if c == q => quote = None,
+                _ => {}
+            }
+            declaration.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        Ok(true)
+    }
+
+    // quotes of the replacement text don't end the literal
+    fn expand_in_literal(
+        &mut self,
+        text: &str,
+        literal: &mut String,
+    ) -> Result<bool, error::ErrorKind> {
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if let Some((name, after)) = parameter_reference(rest) {
+                let Some((replacement, _)) = self.parameter_text(name)? else {
+                    return Ok(false);
+                };
+                self.open.push(name.to_owned());
+                let expanded = self.expand_in_literal(&replacement, literal)?;
+                self.open.pop();
+                if !expanded {
+                    return Ok(false);
+                }
+                rest = after;
+                continue;
+            }
+            match c {
+                '"' => literal.push_str("&#34;"),
+                '\'' => literal.push_str("&#39;"),
+                '%' => literal.push_str("&#37;"),
+                _ => literal.push(c),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        Ok(true)
+    }
+
+    // after `<![`, whether the section is included and the text after its `[`
+    fn conditional_section<'t>(
+        &mut self,
+        text: &'t str,
+    ) -> Result<(bool, &'t str), error::ErrorKind> {
+        let text = text.trim_start_matches(XML_WHITESPACE);
+        let (keyword, rest) = match parameter_reference(text) {
+            Some((name, rest)) => match self.parameter_text(name)? {
+                Some((replacement, _)) => (replacement.trim().to_owned(), rest),
+                // can't be known, it is ignored
+                None => ("IGNORE".to_owned(), rest),
+            },
+            None => {
+                let end = text
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(text.len());
+                (text[..end].to_owned(), &text[end..])
+            }
+        };
+        let include = match keyword.as_str() {
+            "INCLUDE" => true,
+            "IGNORE" => false,
+            _ => return Err(syntax_error("INCLUDE or IGNORE", text.as_bytes())),
+        };
+        match rest.trim_start_matches(XML_WHITESPACE).strip_prefix('[') {
+            Some(rest) => Ok((include, rest)),
+            None => Err(syntax_error("[", rest.as_bytes())),
+        }
+    }
+}
+
+const XML_WHITESPACE: [char; 4] = [' ', '\t', '\r', '\n'];
+
+// `%name;` at the start of the text
+fn parameter_reference(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix('%')?;
+    match name(rest.as_bytes()) {
+        Ok(([b';', ..], entity)) => Some((&rest[..entity.len()], &rest[entity.len() + 1..])),
+        _ => None,
+    }
+}
+
+// text after the `]]>` of an ignored section, nested sections are ignored as well
+fn ignore_section(text: &str) -> Result<&str, error::ErrorKind> {
+    let mut depth = 1;
+    let mut rest = text;
+    loop {
+        let open = rest.find("<![");
+        let Some(close) = rest.find("]]>") else {
+            return Err(syntax_error("]]>", text.as_bytes()));
+        };
+        match open {
+            Some(open) if open < close => {
+                depth += 1;
+                rest = &rest[open + 3..];
+            }
+            _ => {
+                depth -= 1;
+                rest = &rest[close + 3..];
+                if depth == 0 {
+                    return Ok(rest);
+                }
+            }
+        }
+    }
+}
+
+// length of the markup declaration, comment or processing instruction at the start
+fn declaration_end(text: &str) -> Option<usize> {
+    if let Some(comment) = text.strip_prefix("<!--") {
+        return comment.find("-->").map(|end| end + 7);
+    }
+    if text.starts_with("<?") {
+        return text.find("?>").map(|end| end + 2);
+    }
+    if !text.starts_with("<!") {
+        return None;
+    }
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match quote {
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return Some(i + 1),
+            Some(q) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+// the internal subset, then the external subset through the entity resolver
+pub(crate) fn read_dtd(
+    doctype: &xml_sax::DocType,
+    standalone: bool,
+    entities: &mut Entities,
+    attribute_defaults: &mut AttributeDefaults,
+) -> Result<Dtd, error::ErrorKind> {
+    let base_uri = entities.base_uri.clone();
+    let mut reader = SubsetReader::new(entities, attribute_defaults, standalone);
+    if let Some(internal_subset) = doctype.internal_subset {
+        reader.read(internal_subset, false, base_uri.as_deref())?;
+    }
+    let has_pe_references = reader.has_pe_references;
+
+    if let Some(system_id) = doctype.system_id {
+        let external = reader
+            .entities
+            .load(doctype.public_id, system_id, base_uri.as_deref())?;
+        if let Some(external) = external {
+            let base_uri = resolve_uri(system_id, base_uri.as_deref());
+            reader.read(&external, true, Some(&base_uri))?;
+        }
+    }
+
+    let declarations = reader.declarations;
+    entities.must_be_declared = standalone || (doctype.system_id.is_none() && !has_pe_references);
+    Ok(Dtd {
+        name: doctype.name.to_owned(),
+        public_id: doctype.public_id.map(|id| id.to_owned()),
+        system_id: doctype.system_id.map(|id| id.to_owned()),
+        declarations,
+    })
+}
+
+#[cfg(test)]
+fn subset_declarations(text: &str, external: bool) -> Result<Vec<MarkupDecl>, error::ErrorKind> {
+    let mut entities = Entities::new();
+    let mut attribute_defaults = AttributeDefaults::default();
+    let mut reader = SubsetReader::new(&mut entities, &mut attribute_defaults, false);
+    reader.read(text, external, None)?;
+    Ok(reader.declarations)
+}
+
+#[test]
+fn test_parameter_reference() {
+    assert_eq!(parameter_reference("%a:b:c; x"), Some(("a:b:c", " x")));
+    assert_eq!(parameter_reference("%a b;"), None);
+    assert_eq!(parameter_reference("%a"), None);
+}
+
+#[test]
+fn test_subset_reader() {
+    let internal = r#"
+<!ENTITY % decls "<!ENTITY a 'x'><!ELEMENT e EMPTY>">
+<!-- comment with ' and > -->
+%decls;
+%undeclared;
+<!ENTITY b 'y'>
+<!ATTLIST e c CDATA 'z'>
+<!ELEMENT f EMPTY>"#;
+    let mut entities = Entities::new();
+    let mut attribute_defaults = AttributeDefaults::default();
+    let mut reader = SubsetReader::new(&mut entities, &mut attribute_defaults, false);
+    reader.read(internal, false, None).unwrap();
+    assert!(reader.has_pe_references && reader.skipped_reference);
+    let declarations = std::mem::take(&mut reader.declarations);
+    assert_eq!(declarations.len(), 4);
+    assert!(matches!(&declarations[3], MarkupDecl::Element(el) if el.name == "f"));
+    assert!(attribute_defaults.elements.is_empty());
+    assert_eq!(entities.replacement("a"), Some("x"));
+    // not processed after the undeclared reference
+    assert_eq!(entities.replacement("b"), None);
+
+    let external = r#"
+<!ENTITY % draft 'INCLUDE'>
+<!ENTITY % attrs "id ID #IMPLIED title CDATA 'a &#37; b'">
+<!ENTITY % quoted '"q"'>
+<![%draft;[
+  <!ATTLIST e %attrs;>
+  <![ IGNORE [ <!ELEMENT ignored ANY> <![INCLUDE[ ]]> ]]>
+]]>
+<![IGNORE[ <!ELEMENT ignored ANY> ]]>
+<!ENTITY v "value %quoted; %attrs;">
+<!ENTITY s SYSTEM "%attrs;">"#;
+    let declarations = subset_declarations(external, true).unwrap();
+    let entity = |i: usize| match &declarations[i] {
+        MarkupDecl::Entity(decl) => decl.definition.clone(),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(declarations.len(), 6);
+    match &declarations[3] {
+        MarkupDecl::Attlist(list) => {
+            assert_eq!(list.attributes.len(), 2);
+            assert_eq!(
+                list.attributes[1].default,
+                DefaultDecl::Value("a % b".to_owned())
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        entity(4),
+        EntityDef::Internal(
+            "value &#34;q&#34; id ID #IMPLIED title CDATA &#39;a &#37; b&#39;".to_owned()
+        )
+    );
+    assert!(matches!(
+        entity(5),
+        EntityDef::External { external_id, .. } if external_id.system_id == "%attrs;"
+    ));
+
+    assert!(subset_declarations("<![INCLUDE[ <!ELEMENT e ANY>", true).is_err());
+    assert!(subset_declarations("<![INCLUDE[ ]]>", false).is_err());
+    assert!(subset_declarations("<!ENTITY % e 'x'><!ELEMENT e %e;>", false).is_err());
+    assert_eq!(
+        subset_declarations("<!ENTITY % r '%r;'>%r;", false),
+        Err(error::ErrorKind::RecursiveEntity {
+            name: "%r".to_owned()
+        })
+    );
+}
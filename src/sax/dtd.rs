@@ -2,8 +2,13 @@
 
 use std::ops::Mul;
 
+use super::declaration::{
+    AttDef, AttType, AttlistDecl, ContentParticle, ContentSpec, DefaultDecl, ElementDecl,
+    EntityDecl, EntityDef, ExternalId, MarkupDecl, NotationDecl, ParticleKind, Repetition,
+};
 use super::internal::{
-    is_xml_char_t, many0_custom_trycomplete, many1_custom, name, utf8_char_width, Comment, PI,
+    first_char, is_xml_char_t, many0_custom_trycomplete, many1_custom, name, utf8_char_width,
+    Comment, PI,
 };
 
 use nom::error_position;
@@ -18,6 +23,7 @@ use nom::{
     },
     combinator::{map, opt, recognize},
     error::{Error, ErrorKind, ParseError},
+    multi::{many0, many1, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     Err, IResult, InputLength, Needed, Offset, Parser,
 };
@@ -59,8 +65,8 @@ fn inside_doctypedecl_single_pure(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
 
@@ -180,6 +186,28 @@ fn test_ExternalID_75() {
     );
 }
 
+// public and system literals of ExternalID
+type ExternalIdValues<'a> = (Option<&'a [u8]>, &'a [u8]);
+
+fn ExternalID_75_values(input: &[u8]) -> IResult<&[u8], ExternalIdValues<'_>> {
+    alt((
+        map(
+            preceded(tuple((tag("SYSTEM"), multispace1)), SystemLiteral_11),
+            |system_id| (None, system_id),
+        ),
+        map(
+            tuple((
+                tag("PUBLIC"),
+                multispace1,
+                PubidLiteral_12,
+                multispace1,
+                SystemLiteral_11,
+            )),
+            |(_, _, public_id, _, system_id)| (Some(public_id), system_id),
+        ),
+    ))(input)
+}
+
 // [69] PEReference ::= '%' Name ';'
 fn PEReference_69(input: &[u8]) -> IResult<&[u8], &[u8]> {
     recognize(tuple((tag("%"), name, tag(";"))))(input)
@@ -772,3 +800,523 @@ fn test_doctypedecl() {
         Ok((&b"dummy"[..], &br#"<!DOCTYPE test  [ ]>"#[..]))
     );
 }
+
+pub(crate) struct DocTypeValues<'a> {
+    pub name: &'a [u8],
+    pub public_id: Option<&'a [u8]>,
+    pub system_id: Option<&'a [u8]>,
+    pub internal_subset: Option<&'a [u8]>,
+}
+
+// fields of a doctypedecl recognized by `doctypedecl`
+pub(crate) fn doctypedecl_values(input: &[u8]) -> IResult<&[u8], DocTypeValues<'_>> {
+    map(
+        tuple((
+            doctypedecl_start,
+            multispace1,
+            name,
+            opt(preceded(multispace1, ExternalID_75_values)),
+            multispace0,
+            opt(terminated(
+                delimited(tag("["), intSubset_28b, tag("]")),
+                multispace0,
+            )),
+            doctypedecl_end,
+        )),
+        |(_, _, name, external_id, _, internal_subset, _)| DocTypeValues {
+            name,
+            public_id: external_id.and_then(|(public_id, _)| public_id),
+            system_id: external_id.map(|(_, system_id)| system_id),
+            internal_subset,
+        },
+    )(input)
+}
+
+#[test]
+fn test_doctypedecl_values() {
+    let (_, values) = doctypedecl_values(
+        br#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" 'xhtml1-strict.dtd' [ <!-- --> ]>"#,
+    )
+    .unwrap();
+    assert_eq!(values.name, b"html");
+    assert_eq!(
+        values.public_id,
+        Some(&b"-//W3C//DTD XHTML 1.0 Strict//EN"[..])
+    );
+    assert_eq!(values.system_id, Some(&b"xhtml1-strict.dtd"[..]));
+    assert_eq!(values.internal_subset, Some(&b" <!-- --> "[..]));
+
+    let (_, values) = doctypedecl_values(br#"<!DOCTYPE greeting SYSTEM "hello.dtd">"#).unwrap();
+    assert_eq!(values.name, b"greeting");
+    assert_eq!(values.public_id, None);
+    assert_eq!(values.system_id, Some(&b"hello.dtd"[..]));
+    assert_eq!(values.internal_subset, None);
+}
+
+fn to_string(input: &[u8]) -> String {
+    String::from_utf8_lossy(input).into_owned()
+}
+
+fn external_id((public_id, system_id): ExternalIdValues<'_>) -> ExternalId {
+    ExternalId {
+        public_id: public_id.map(to_string),
+        system_id: to_string(system_id),
+    }
+}
+
+// ('?' | '*' | '+')?
+fn repetition_values(input: &[u8]) -> IResult<&[u8], Repetition> {
+    map(opt(alt((char('?'), char('*'), char('+')))), |c| match c {
+        Some('?') => Repetition::Optional,
+        Some('*') => Repetition::ZeroOrMore,
+        Some('+') => Repetition::OneOrMore,
+        _ => Repetition::Once,
+    })(input)
+}
+
+// [48] cp ::= (Name | choice | seq) ('?' | '*' | '+')?
+fn cp_48_values(input: &[u8]) -> IResult<&[u8], ContentParticle> {
+    map(
+        pair(
+            alt((
+                map(name, |name| ParticleKind::Name(to_string(name))),
+                choice_or_seq_values,
+            )),
+            repetition_values,
+        ),
+        |(kind, repetition)| ContentParticle { kind, repetition },
+    )(input)
+}
+
+// [49] choice ::= '(' S? cp ( S? '|' S? cp )+ S? ')'
+// [50] seq ::= '(' S? cp ( S? ',' S? cp )* S? ')'
+fn choice_or_seq_values(input: &[u8]) -> IResult<&[u8], ParticleKind> {
+    let (input, first) = preceded(pair(tag("("), multispace0), cp_48_values)(input)?;
+    let (input, (is_choice, rest)) = alt((
+        map(
+            many1(preceded(
+                tuple((multispace0, tag("|"), multispace0)),
+                cp_48_values,
+            )),
+            |rest| (true, rest),
+        ),
+        map(
+            many0(preceded(
+                tuple((multispace0, tag(","), multispace0)),
+                cp_48_values,
+            )),
+            |rest| (false, rest),
+        ),
+    ))(input)?;
+    let (input, _) = pair(multispace0, tag(")"))(input)?;
+
+    let mut list = vec![first];
+    list.extend(rest);
+    if is_choice {
+        Ok((input, ParticleKind::Choice(list)))
+    } else {
+        Ok((input, ParticleKind::Seq(list)))
+    }
+}
+
+// [51] Mixed ::= '(' S? '#PCDATA' (S? '|' S? Name)* S? ')*' | '(' S? '#PCDATA' S? ')'
+fn Mixed_51_values(input: &[u8]) -> IResult<&[u8], ContentSpec> {
+    alt((
+        map(
+            delimited(
+                tuple((tag("("), multispace0, tag("#PCDATA"))),
+                many0(preceded(tuple((multispace0, tag("|"), multispace0)), name)),
+                pair(multispace0, tag(")*")),
+            ),
+            |names| ContentSpec::Mixed(names.into_iter().map(to_string).collect()),
+        ),
+        map(
+            tuple((tag("("), multispace0, tag("#PCDATA"), multispace0, tag(")"))),
+            |_| ContentSpec::Mixed(Vec::new()),
+        ),
+    ))(input)
+}
+
+// [46] contentspec ::= 'EMPTY' | 'ANY' | Mixed | children
+fn contentspec_46_values(input: &[u8]) -> IResult<&[u8], ContentSpec> {
+    alt((
+        map(tag("EMPTY"), |_| ContentSpec::Empty),
+        map(tag("ANY"), |_| ContentSpec::Any),
+        Mixed_51_values,
+        map(
+            pair(choice_or_seq_values, repetition_values),
+            |(kind, repetition)| ContentSpec::Children(ContentParticle { kind, repetition }),
+        ),
+    ))(input)
+}
+
+// [45] elementdecl ::= '<!ELEMENT' S Name S contentspec S? '>'
+fn elementdecl_45_values(input: &[u8]) -> IResult<&[u8], ElementDecl> {
+    map(
+        tuple((
+            tag("<!ELEMENT"),
+            multispace1,
+            name,
+            multispace1,
+            contentspec_46_values,
+            multispace0,
+            tag(">"),
+        )),
+        |(_, _, name, _, content_spec, _, _)| ElementDecl {
+            name: to_string(name),
+            content_spec,
+        },
+    )(input)
+}
+
+// '(' S? token (S? '|' S? token)* S? ')'
+fn token_list_values<'a>(
+    token: impl FnMut(&'a [u8]) -> IResult<&'a [u8], &'a [u8]>,
+    input: &'a [u8],
+) -> IResult<&'a [u8], Vec<String>> {
+    delimited(
+        pair(tag("("), multispace0),
+        separated_list1(
+            tuple((multispace0, tag("|"), multispace0)),
+            map(token, to_string),
+        ),
+        pair(multispace0, tag(")")),
+    )(input)
+}
+
+// [54] AttType ::= StringType | TokenizedType | EnumeratedType
+fn AttType_54_values(input: &[u8]) -> IResult<&[u8], AttType> {
+    alt((
+        map(tag("CDATA"), |_| AttType::Cdata),
+        map(tag("IDREFS"), |_| AttType::IdRefs),
+        map(tag("IDREF"), |_| AttType::IdRef),
+        map(tag("ID"), |_| AttType::Id),
+        map(tag("ENTITY"), |_| AttType::Entity),
+        map(tag("ENTITIES"), |_| AttType::Entities),
+        map(tag("NMTOKENS"), |_| AttType::NmTokens),
+        map(tag("NMTOKEN"), |_| AttType::NmToken),
+        map(
+            preceded(pair(tag("NOTATION"), multispace1), |i| {
+                token_list_values(name, i)
+            }),
+            AttType::Notation,
+        ),
+        map(|i| token_list_values(Nmtoken_7, i), AttType::Enumeration),
+    ))(input)
+}
+
+// [60] DefaultDecl ::= '#REQUIRED' | '#IMPLIED' | (('#FIXED' S)? AttValue)
+fn DefaultDecl_60_values(input: &[u8]) -> IResult<&[u8], DefaultDecl> {
+    alt((
+        map(tag("#REQUIRED"), |_| DefaultDecl::Required),
+        map(tag("#IMPLIED"), |_| DefaultDecl::Implied),
+        map(
+            preceded(pair(tag("#FIXED"), multispace1), super::internal::AttValue),
+            |value| DefaultDecl::Fixed(to_string(value)),
+        ),
+        map(super::internal::AttValue, |value| {
+            DefaultDecl::Value(to_string(value))
+        }),
+    ))(input)
+}
+
+// [53] AttDef ::= S Name S AttType S DefaultDecl
+fn AttDef_53_values(input: &[u8]) -> IResult<&[u8], AttDef> {
+    map(
+        tuple((
+            multispace1,
+            name,
+            multispace1,
+            AttType_54_values,
+            multispace1,
+            DefaultDecl_60_values,
+        )),
+        |(_, name, _, att_type, _, default)| AttDef {
+            name: to_string(name),
+            att_type,
+            default,
+        },
+    )(input)
+}
+
+// [52] AttlistDecl ::= '<!ATTLIST' S Name AttDef* S? '>'
+fn AttlistDecl_52_values(input: &[u8]) -> IResult<&[u8], AttlistDecl> {
+    map(
+        tuple((
+            tag("<!ATTLIST"),
+            multispace1,
+            name,
+            many0(AttDef_53_values),
+            multispace0,
+            tag(">"),
+        )),
+        |(_, _, element, attributes, _, _)| AttlistDecl {
+            element: to_string(element),
+            attributes,
+        },
+    )(input)
+}
+
+// [73] EntityDef ::= EntityValue | (ExternalID NDataDecl?)
+fn EntityDef_73_values(input: &[u8]) -> IResult<&[u8], EntityDef> {
+    alt((
+        map(
+            pair(
+                ExternalID_75_values,
+                opt(preceded(
+                    tuple((multispace1, tag("NDATA"), multispace1)),
+                    name,
+                )),
+            ),
+            |(id, notation)| EntityDef::External {
+                external_id: external_id(id),
+                notation: notation.map(to_string),
+            },
+        ),
+        map(EntityValue, |value| EntityDef::Internal(to_string(value))),
+    ))(input)
+}
+
+// [74] PEDef ::= EntityValue | ExternalID
+fn PEDef_74_values(input: &[u8]) -> IResult<&[u8], EntityDef> {
+    alt((
+        map(ExternalID_75_values, |id| EntityDef::External {
+            external_id: external_id(id),
+            notation: None,
+        }),
+        map(EntityValue, |value| EntityDef::Internal(to_string(value))),
+    ))(input)
+}
+
+// [70] EntityDecl ::= GEDecl | PEDecl
+fn EntityDecl_70_values(input: &[u8]) -> IResult<&[u8], EntityDecl> {
+    alt((
+        map(
+            tuple((
+                tag("<!ENTITY"),
+                multispace1,
+                name,
+                multispace1,
+                EntityDef_73_values,
+                multispace0,
+                tag(">"),
+            )),
+            |(_, _, name, _, definition, _, _)| EntityDecl {
+                name: to_string(name),
+                parameter: false,
+                definition,
+            },
+        ),
+        map(
+            tuple((
+                tag("<!ENTITY"),
+                multispace1,
+                tag("%"),
+                multispace1,
+                name,
+                multispace1,
+                PEDef_74_values,
+                multispace0,
+                tag(">"),
+            )),
+            |(_, _, _, _, name, _, definition, _, _)| EntityDecl {
+                name: to_string(name),
+                parameter: true,
+                definition,
+            },
+        ),
+    ))(input)
+}
+
+// [82] NotationDecl ::= '<!NOTATION' S Name S (ExternalID | PublicID) S? '>'
+fn NotationDecl_values(input: &[u8]) -> IResult<&[u8], NotationDecl> {
+    map(
+        tuple((
+            tag("<!NOTATION"),
+            multispace1,
+            name,
+            multispace1,
+            alt((
+                map(ExternalID_75_values, |(public_id, system_id)| {
+                    (public_id, Some(system_id))
+                }),
+                map(
+                    preceded(pair(tag("PUBLIC"), multispace1), PubidLiteral_12),
+                    |public_id| (Some(public_id), None),
+                ),
+            )),
+            multispace0,
+            tag(">"),
+        )),
+        |(_, _, name, _, (public_id, system_id), _, _)| NotationDecl {
+            name: to_string(name),
+            public_id: public_id.map(to_string),
+            system_id: system_id.map(to_string),
+        },
+    )(input)
+}
+
+// [29] markupdecl ::= elementdecl | AttlistDecl | EntityDecl | NotationDecl | PI | Comment
+// PIs and comments give `None`
+pub(crate) fn markupdecl_29_values(input: &[u8]) -> IResult<&[u8], Option<MarkupDecl>> {
+    alt((
+        map(elementdecl_45_values, |decl| {
+            Some(MarkupDecl::Element(decl))
+        }),
+        map(AttlistDecl_52_values, |decl| {
+            Some(MarkupDecl::Attlist(decl))
+        }),
+        map(EntityDecl_70_values, |decl| Some(MarkupDecl::Entity(decl))),
+        map(NotationDecl_values, |decl| Some(MarkupDecl::Notation(decl))),
+        map(alt((PI, Comment)), |_| None),
+    ))(input)
+}
+
+#[test]
+fn test_markupdecl_29_values() {
+    let data = r#"
+<!ELEMENT doc (head?, (p | list)+)>
+<!ELEMENT p (#PCDATA | em)*>
+<!ELEMENT br EMPTY>
+<!-- comment -->
+<!ATTLIST doc
+  id ID #REQUIRED
+  type (a | b) "a"
+  img NOTATION (gif) #IMPLIED
+  version CDATA #FIXED '1.0'>
+<?pi data?>
+<!ENTITY e "&#60;foo>">
+<!ENTITY % pe SYSTEM "pe.dtd">
+%pe;
+<!ENTITY pic PUBLIC "-//pic" "pic.gif" NDATA gif>
+<!NOTATION gif PUBLIC "image/gif">
+"#;
+    let mut input = data.as_bytes();
+    let mut decls = Vec::new();
+    let mut has_pe_references = false;
+    loop {
+        let space = input
+            .iter()
+            .take_while(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n'))
+            .count();
+        input = &input[space..];
+        if input.is_empty() {
+            break;
+        }
+        if let Ok((rest, _)) = PEReference_69(input) {
+            has_pe_references = true;
+            input = rest;
+            continue;
+        }
+        let (rest, decl) = markupdecl_29_values(input).unwrap();
+        decls.extend(decl);
+        input = rest;
+    }
+    assert!(has_pe_references);
+    assert_eq!(decls.len(), 8);
+
+    let particle = |kind, repetition| ContentParticle { kind, repetition };
+    let name = |name: &str| particle(ParticleKind::Name(name.to_owned()), Repetition::Once);
+    assert_eq!(
+        decls[0],
+        MarkupDecl::Element(ElementDecl {
+            name: "doc".to_owned(),
+            content_spec: ContentSpec::Children(particle(
+                ParticleKind::Seq(vec![
+                    particle(ParticleKind::Name("head".to_owned()), Repetition::Optional),
+                    particle(
+                        ParticleKind::Choice(vec![name("p"), name("list")]),
+                        Repetition::OneOrMore
+                    ),
+                ]),
+                Repetition::Once
+            )),
+        })
+    );
+    assert_eq!(
+        decls[1],
+        MarkupDecl::Element(ElementDecl {
+            name: "p".to_owned(),
+            content_spec: ContentSpec::Mixed(vec!["em".to_owned()]),
+        })
+    );
+    assert_eq!(
+        decls[2],
+        MarkupDecl::Element(ElementDecl {
+            name: "br".to_owned(),
+            content_spec: ContentSpec::Empty,
+        })
+    );
+    assert_eq!(
+        decls[3],
+        MarkupDecl::Attlist(AttlistDecl {
+            element: "doc".to_owned(),
+            attributes: vec![
+                AttDef {
+                    name: "id".to_owned(),
+                    att_type: AttType::Id,
+                    default: DefaultDecl::Required,
+                },
+                AttDef {
+                    name: "type".to_owned(),
+                    att_type: AttType::Enumeration(vec!["a".to_owned(), "b".to_owned()]),
+                    default: DefaultDecl::Value("a".to_owned()),
+                },
+                AttDef {
+                    name: "img".to_owned(),
+                    att_type: AttType::Notation(vec!["gif".to_owned()]),
+                    default: DefaultDecl::Implied,
+                },
+                AttDef {
+                    name: "version".to_owned(),
+                    att_type: AttType::Cdata,
+                    default: DefaultDecl::Fixed("1.0".to_owned()),
+                },
+            ],
+        })
+    );
+    assert_eq!(
+        decls[4],
+        MarkupDecl::Entity(EntityDecl {
+            name: "e".to_owned(),
+            parameter: false,
+            definition: EntityDef::Internal("&#60;foo>".to_owned()),
+        })
+    );
+    assert_eq!(
+        decls[5],
+        MarkupDecl::Entity(EntityDecl {
+            name: "pe".to_owned(),
+            parameter: true,
+            definition: EntityDef::External {
+                external_id: ExternalId {
+                    public_id: None,
+                    system_id: "pe.dtd".to_owned(),
+                },
+                notation: None,
+            },
+        })
+    );
+    assert_eq!(
+        decls[6],
+        MarkupDecl::Entity(EntityDecl {
+            name: "pic".to_owned(),
+            parameter: false,
+            definition: EntityDef::External {
+                external_id: ExternalId {
+                    public_id: Some("-//pic".to_owned()),
+                    system_id: "pic.gif".to_owned(),
+                },
+                notation: Some("gif".to_owned()),
+            },
+        })
+    );
+    assert_eq!(
+        decls[7],
+        MarkupDecl::Notation(NotationDecl {
+            name: "gif".to_owned(),
+            public_id: Some("image/gif".to_owned()),
+            system_id: None,
+        })
+    );
+}
@@ -0,0 +1,393 @@
+// Encoding detection and transcoding to UTF-8, XML 1.0 Appendix F
+
+use crate::sax::parser::error::ErrorKind;
+
+mod tables;
+
+/// A single-byte encoding, the lower half is ASCII.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct SingleByte {
+    // the preferred name first
+    names: &'static [&'static str],
+    high: [char; 128],
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    SingleByte(&'static SingleByte),
+}
+
+impl Encoding {
+    /// Encoding of a name as in the XML declaration, names are case insensitive.
+    /// `UTF-16` is big-endian unless a byte order mark says otherwise.
+    pub(crate) fn from_name(name: &str) -> Option<Encoding> {
+        let is = |n: &str| n.eq_ignore_ascii_case(name);
+        if is("UTF-8") || is("UTF8") {
+            return Some(Encoding::Utf8);
+        }
+        if is("UTF-16") || is("UTF-16BE") {
+            return Some(Encoding::Utf16Be);
+        }
+        if is("UTF-16LE") {
+            return Some(Encoding::Utf16Le);
+        }
+        tables::TABLES
+            .iter()
+            .find(|table| table.names.iter().any(|n| is(n)))
+            .map(|table| Encoding::SingleByte(table))
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::SingleByte(table) => table.names[0],
+        }
+    }
+
+    // length of the UTF-8 text in the input
+    pub(crate) fn input_len(self, utf8: &[u8]) -> usize {
+        let chars = utf8.iter().filter(|&&b| b & 0xC0 != 0x80);
+        match self {
+            Encoding::Utf8 => utf8.len(),
+            // characters out of the BMP are a surrogate pair
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                chars.map(|&b| if b >= 0xF0 { 4 } else { 2 }).sum()
+            }
+            Encoding::SingleByte(_) => chars.count(),
+        }
+    }
+
+    fn is_utf16(self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+}
+
+// Appendix F.1, the encoding and the length of the byte order mark
+fn sniff(bytes: &[u8]) -> Result<(Encoding, usize), ErrorKind> {
+    let unsupported = |encoding: &str| {
+        Err(ErrorKind::UnsupportedEncoding {
+            encoding: encoding.to_owned(),
+        })
+    };
+    match bytes {
+        [0x00, 0x00, 0xFE, 0xFF, ..]
+        | [0xFF, 0xFE, 0x00, 0x00, ..]
+        | [0x00, 0x00, 0xFF, 0xFE, ..]
+        | [0xFE, 0xFF, 0x00, 0x00, ..]
+        | [0x00, 0x00, 0x00, 0x3C, ..]
+        | [0x3C, 0x00, 0x00, 0x00, ..]
+        | [0x00, 0x00, 0x3C, 0x00, ..]
+        | [0x00, 0x3C, 0x00, 0x00, ..] => unsupported("UCS-4"),
+        [0x4C, 0x6F, 0xA7, 0x94, ..] => unsupported("EBCDIC"),
+        [0xEF, 0xBB, 0xBF, ..] => Ok((Encoding::Utf8, 3)),
+        [0xFE, 0xFF, ..] => Ok((Encoding::Utf16Be, 2)),
+        [0xFF, 0xFE, ..] => Ok((Encoding::Utf16Le, 2)),
+        // `<?` without a byte order mark
+        [0x00, 0x3C, 0x00, 0x3F, ..] => Ok((Encoding::Utf16Be, 0)),
+        [0x3C, 0x00, 0x3F, 0x00, ..] => Ok((Encoding::Utf16Le, 0)),
+        _ => Ok((Encoding::Utf8, 0)),
+    }
+}
+
+/// Input that can't be decoded, `offset` is the position of the bad bytes in the input.
+#[derive(Debug)]
+pub(crate) struct DecodeError {
+    pub(crate) kind: ErrorKind,
+    pub(crate) offset: usize,
+}
+
+/// Detects the encoding from the first bytes of the input and transcodes it to UTF-8.
+/// UTF-8 input is passed through as it is, the parser checks it.
+#[derive(Default)]
+pub(crate) struct Decoder {
+    // `None` until four bytes or the whole input is seen
+    encoding: Option<Encoding>,
+    bom_len: usize,
+    // input bytes before `pending`
+    offset: usize,
+    // set from the builder, an unknown name is kept for the error
+    forced: Option<Result<Encoding, String>>,
+    // input that is not decoded yet, e.g. half of a UTF-16 code unit
+    pending: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Uses the named encoding instead of detecting it, the XML declaration is not
+    /// checked. A byte order mark of the encoding is skipped.
+    pub(crate) fn force(&mut self, name: &str) {
+        self.forced = Some(Encoding::from_name(name).ok_or_else(|| name.to_owned()));
+    }
+
+    /// The detected encoding and the length of its byte order mark.
+    pub(crate) fn encoding(&self) -> Option<(Encoding, usize)> {
+        self.encoding.map(|encoding| (encoding, self.bom_len))
+    }
+
+    /// UTF-8 of the next input, `eof` is set after the last input.
+    pub(crate) fn decode<'a>(
+        &'a mut self,
+        input: &'a [u8],
+        eof: bool,
+    ) -> Result<&'a [u8], DecodeError> {
+        let input = match self.encoding {
+            Some(Encoding::Utf8) => {
+                self.offset += input.len();
+                return Ok(input);
+            }
+            Some(_) => input,
+            None => {
+                self.pending.extend_from_slice(input);
+                if self.pending.len() < 4 && !eof {
+                    return Ok(&[]);
+                }
+                let (encoding, bom_len) = self.detect().map_err(|kind| DecodeError {
+                    kind,
+                    offset: self.offset,
+                })?;
+                self.encoding = Some(encoding);
+                self.bom_len = bom_len;
+                self.offset += bom_len;
+                self.pending.drain(..bom_len);
+                &[]
+            }
+        };
+
+        self.output.clear();
+        match self.encoding.unwrap_or_default() {
+            // right after the detection
+            Encoding::Utf8 => {
+                self.offset += self.pending.len();
+                self.output.append(&mut self.pending);
+            }
+            encoding @ (Encoding::Utf16Le | Encoding::Utf16Be) => {
+                self.decode_utf16(encoding, input, eof)?
+            }
+            Encoding::SingleByte(table) => {
+                let pending = std::mem::take(&mut self.pending);
+                self.decode_single_byte(table, &pending)?;
+                self.decode_single_byte(table, input)?;
+            }
+        }
+        Ok(&self.output)
+    }
+
+    fn detect(&self) -> Result<(Encoding, usize), ErrorKind> {
+        match &self.forced {
+            None => sniff(&self.pending),
+            Some(Err(name)) => Err(ErrorKind::UnsupportedEncoding {
+                encoding: name.clone(),
+            }),
+            // the byte order of UTF-16 comes from the input
+            Some(Ok(forced)) => match sniff(&self.pending) {
+                Ok((detected, bom_len))
+                    if detected == *forced || (detected.is_utf16() && forced.is_utf16()) =>
+                {
+                    Ok((detected, bom_len))
+                }
+                _ => Ok((*forced, 0)),
+            },
+        }
+    }
+
+    /// UTF-8 of the input before the bad bytes after `decode` fails.
+    pub(crate) fn decoded(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The last `len` bytes were passed through as UTF-8 before the encoding
+    /// declaration switched the encoding, they are decoded again.
+    pub(crate) fn rewind(&mut self, len: usize) {
+        self.offset -= len;
+    }
+
+    fn decode_single_byte(&mut self, table: &SingleByte, input: &[u8]) -> Result<(), DecodeError> {
+        for &b in input {
+            if b >= 0x80 {
+                let c = table.high[b as usize - 0x80];
+                if c == '\u{FFFD}' {
+                    return Err(DecodeError {
+                        kind: ErrorKind::InvalidEncoding {
+                            encoding: table.names[0],
+                        },
+                        offset: self.offset,
+                    });
+                }
+                let mut utf8 = [0; 4];
+                self.output
+                    .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            } else {
+                self.output.push(b);
+            }
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    fn decode_utf16(
+        &mut self,
+        encoding: Encoding,
+        input: &[u8],
+        eof: bool,
+    ) -> Result<(), DecodeError> {
+        let invalid = |offset| DecodeError {
+            kind: ErrorKind::InvalidEncoding {
+                encoding: encoding.name(),
+            },
+            offset,
+        };
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(input);
+
+        let whole = data.len() / 2 * 2;
+        let units = data[..whole].chunks_exact(2).map(|pair| match encoding {
+            Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+            _ => u16::from_le_bytes([pair[0], pair[1]]),
+        });
+        let mut consumed = 0;
+        for c in char::decode_utf16(units) {
+            match c {
+                Ok(c) => {
+                    let mut utf8 = [0; 4];
+                    self.output
+                        .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                    consumed += c.len_utf16() * 2;
+                }
+                // the low surrogate comes with the next input
+                Err(err)
+                    if !eof
+                        && consumed + 2 == whole
+                        && (0xD800..0xDC00).contains(&err.unpaired_surrogate()) =>
+                {
+                    break;
+                }
+                Err(_) => return Err(invalid(self.offset + consumed)),
+            }
+        }
+        if eof && consumed < data.len() {
+            return Err(invalid(self.offset + consumed));
+        }
+        self.offset += consumed;
+        data.drain(..consumed);
+        self.pending = data;
+        Ok(())
+    }
+
+    /// Checks the encoding name of the XML declaration against the detected encoding.
+    /// A single-byte encoding is used from now on if only ASCII is read so far, the
+    /// result is `true` then and the input after the declaration has to be decoded again.
+    pub(crate) fn declared(&mut self, name: &str) -> Result<bool, ErrorKind> {
+        if self.forced.is_some() {
+            return Ok(false);
+        }
+        let Some(declared) = Encoding::from_name(name) else {
+            return Err(ErrorKind::UnsupportedEncoding {
+                encoding: name.to_owned(),
+            });
+        };
+        let detected = self.encoding.unwrap_or_default();
+        match declared {
+            _ if declared == detected => Ok(false),
+            // the byte order mark or the first bytes give the byte order
+            Encoding::Utf16Be if detected.is_utf16() && name.eq_ignore_ascii_case("UTF-16") => {
+                Ok(false)
+            }
+            Encoding::SingleByte(_) if detected == Encoding::Utf8 && self.bom_len == 0 => {
+                self.encoding = Some(declared);
+                Ok(true)
+            }
+            _ => Err(ErrorKind::EncodingMismatch {
+                declared: name.to_owned(),
+                detected: detected.name(),
+            }),
+        }
+    }
+}
+
+#[test]
+fn test_encoding_names() {
+    let name = |label| Encoding::from_name(label).map(Encoding::name);
+    assert_eq!(name("utf8"), Some("UTF-8"));
+    assert_eq!(name("UTF-16"), Some("UTF-16BE"));
+    assert_eq!(name("ascii"), Some("US-ASCII"));
+    for label in ["iso-8859-1", "ISO8859-1", "ISO_8859-1", "latin1", "LATIN1"] {
+        assert_eq!(name(label), Some("ISO-8859-1"), "{}", label);
+    }
+    assert_eq!(name("cp1252"), Some("windows-1252"));
+    assert_eq!(name("latin2"), None);
+}
+
+#[test]
+fn test_sniff() {
+    assert_eq!(sniff(b"<?xm").unwrap(), (Encoding::Utf8, 0));
+    assert_eq!(sniff(b"\xEF\xBB\xBF<").unwrap(), (Encoding::Utf8, 3));
+    assert_eq!(sniff(b"\xFF\xFE<\x00").unwrap(), (Encoding::Utf16Le, 2));
+    assert_eq!(sniff(b"\xFE\xFF\x00<").unwrap(), (Encoding::Utf16Be, 2));
+    assert_eq!(sniff(b"<\x00?\x00").unwrap(), (Encoding::Utf16Le, 0));
+    assert_eq!(sniff(b"\x00<\x00?").unwrap(), (Encoding::Utf16Be, 0));
+    assert!(sniff(b"\x00\x00\x00<").is_err());
+    assert!(sniff(b"\x4C\x6F\xA7\x94").is_err());
+}
+
+#[test]
+fn test_decode_utf16() {
+    let input: Vec<u8> = "\u{FEFF}<a>ç𝄞</a>"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    // every split point, also inside code units and surrogate pairs
+    for split in 0..input.len() {
+        let mut decoder = Decoder::new();
+        let mut text = decoder.decode(&input[..split], false).unwrap().to_vec();
+        text.extend_from_slice(decoder.decode(&input[split..], false).unwrap());
+        text.extend_from_slice(decoder.decode(&[], true).unwrap());
+        assert_eq!(String::from_utf8(text).unwrap(), "<a>ç𝄞</a>");
+        assert_eq!(decoder.encoding(), Some((Encoding::Utf16Le, 2)));
+    }
+
+    assert_eq!(Encoding::Utf16Le.input_len("ç𝄞".as_bytes()), 6);
+
+    let mut decoder = Decoder::new();
+    assert!(decoder.decode(b"\xFF\xFE<\x00\x00\xD8a\x00", true).is_err());
+    let mut decoder = Decoder::new();
+    assert!(decoder.decode(b"\xFF\xFE<\x00a", true).is_err());
+}
+
+#[test]
+fn test_decode_single_byte() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"<?xml", false).unwrap(), b"<?xml");
+    assert!(decoder.declared("windows-1252").unwrap());
+    assert_eq!(
+        decoder.decode(b"\x80 \xE7", true).unwrap(),
+        "€ ç".as_bytes()
+    );
+    assert_eq!(
+        Encoding::from_name("windows-1252")
+            .unwrap()
+            .input_len("€ ç".as_bytes()),
+        3
+    );
+    // undefined in windows-1252
+    assert!(decoder.decode(b"\x81", true).is_err());
+
+    let mut decoder = Decoder::new();
+    decoder.force("iso-8859-15");
+    assert_eq!(decoder.decode(b"\xA4", true).unwrap(), "€".as_bytes());
+    assert!(!decoder.declared("UTF-8").unwrap());
+
+    let mut decoder = Decoder::new();
+    decoder.decode(b"\xEF\xBB\xBF<?xml", false).unwrap();
+    assert!(decoder.declared("ISO-8859-1").is_err());
+    assert!(decoder.declared("KOI8-R").is_err());
+}
@@ -4,7 +4,7 @@ use std::ops::Range;
 
 use nom::{
     branch::alt,
-    bytes::streaming::{is_not, tag, take_while1},
+    bytes::streaming::{is_not, tag, take_while, take_while1},
     character::{
         complete::{char, multispace1},
         is_digit, is_hex_digit,
@@ -47,6 +47,12 @@ pub(crate) fn utf8_char_width(b: u8) -> usize {
     return UTF8_CHAR_WIDTH[b as usize] as usize;
 }
 
+// the character of a UTF-8 sequence, `None` if it is invalid. It is empty for bytes
+// that can't start a sequence, their width is 0
+pub(crate) fn first_char(input: &[u8]) -> Option<char> {
+    std::str::from_utf8(input).ok()?.chars().next()
+}
+
 // enum CharResult {
 //     Char(char, usize),
 //     Err,
@@ -107,7 +113,7 @@ pub(crate) fn is_xml_char_t(chr: char) -> bool {
 // NameStartChar.expected_chars.push(':');
 // NameStartChar.expected_chars.push('_');
 #[inline]
-fn is_namestart_char_t(chr: char) -> bool {
+pub(crate) fn is_namestart_char_t(chr: char) -> bool {
     (chr >= 'A' && chr <= 'Z')
         || (chr >= 'a' && chr <= 'z')
         || (chr >= '\u{C0}' && chr <= '\u{D6}')
@@ -136,8 +142,8 @@ fn namestart_char(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
     // let c = unsafe { std::str::from_utf8_unchecked(&input[..width]) }.chars().next().unwrap();
@@ -151,7 +157,7 @@ fn namestart_char(input: &[u8]) -> IResult<&[u8], &[u8]> {
 
 // [4a] NameChar ::= NameStartChar | "-" | "." | [0-9] | #xB7 | [#x0300-#x036F] | [#x203F-#x2040]
 #[inline]
-fn is_namechar_t(chr: char) -> bool {
+pub(crate) fn is_namechar_t(chr: char) -> bool {
     is_namestart_char_t(chr)
         || (chr >= '0' && chr <= '9')
         || (chr >= '\u{0300}' && chr <= '\u{036F}')
@@ -171,8 +177,8 @@ pub(crate) fn namechar(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
     // let c = unsafe { std::str::from_utf8_unchecked(&input[..width]) }.chars().next().unwrap();
@@ -352,11 +358,13 @@ fn Attribute(input: &[u8]) -> IResult<&[u8], SAXAttribute> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AttributeRange {
     pub value: Range<usize>,
+    pub raw_value: Range<usize>,
     pub name: Range<usize>,
     // namespace aware
     pub local_name: Range<usize>,
     pub prefix: Range<usize>,
     pub namespace: Range<usize>,
+    pub specified: bool,
 }
 
 pub(crate) fn Attribute2(input: &[u8]) -> IResult<&[u8], AttributeRange> {
@@ -374,9 +382,11 @@ pub(crate) fn Attribute2(input: &[u8]) -> IResult<&[u8], AttributeRange> {
                 AttributeRange{
                     name:  std::ops::Range { start:name_start , end: name_end } ,
                     value: (val_start..val_end),
+                    raw_value: (val_start..val_end),
                     local_name: (0..0),
                     prefix: (0..0),
-                    namespace: (0..0) }
+                    namespace: (0..0),
+                    specified: true }
                 // SAXAttribute {
                 //     value: unsafe { std::str::from_utf8_unchecked(o.2) },
                 //     qualified_name: unsafe { std::str::from_utf8_unchecked(o.0) },
@@ -469,6 +479,27 @@ fn STag<'a>(input: &'a [u8]) -> IResult<&[u8], StartElement<'a>> {
     }
 }
 
+// '<' Name (S Attribute)* S?
+// the common beginning of STag and EmptyElemTag, used to find out where a malformed tag fails
+pub(crate) fn STag_prefix(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    match tuple((
+        char('<'),
+        name,
+        many0_custom_trycomplete(preceded(multispace0, Attribute)),
+        multispace0,
+    ))(input)
+    {
+        Ok((i, o)) => Ok((i, o.1)),
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn test_STag_prefix() {
+    let data = r#"<A a="b" c=d>"#.as_bytes();
+    assert_eq!(STag_prefix(&data), Ok((&b"c=d>"[..], &b"A"[..])));
+}
+
 // [44] EmptyElemTag ::= '<' Name (S Attribute)* S? '/>'
 fn EmptyElemTag(input: &[u8]) -> IResult<&[u8], StartElement> {
     match tuple((
@@ -571,8 +602,8 @@ fn CharData_single_pure(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
     // let c = unsafe { std::str::from_utf8_unchecked(&input[..width]) }.chars().next().unwrap();
@@ -791,8 +822,9 @@ fn test_xml3() {
 // Parser Rules organized by W3C Spec
 
 // [26] VersionNum ::= '1.' [0-9]+
+// other versions are recognized too, so they are reported as unsupported
 fn VersionNum(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    recognize(tuple((tag("1."), digit1)))(input)
+    recognize(tuple((digit1, char('.'), digit1)))(input)
 }
 
 #[test]
@@ -804,15 +836,17 @@ fn test_VersionNum() {
 //  [24] VersionInfo ::= S 'version' Eq ("'" VersionNum "'" | '"' VersionNum '"')
 
 fn VersionInfo(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    recognize(tuple((
-        multispace1,
-        tag("version"),
-        Eq,
+    recognize(VersionInfo_value)(input)
+}
+
+fn VersionInfo_value(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    preceded(
+        tuple((multispace1, tag("version"), Eq)),
         alt((
             delimited(char('"'), VersionNum, char('"')),
             delimited(char('\''), VersionNum, char('\'')),
         )),
-    )))(input)
+    )(input)
 }
 #[test]
 fn test_VersionInfo() {
@@ -837,15 +871,17 @@ fn test_EncName() {
 
 // [80] EncodingDecl ::= S 'encoding' Eq ('"' EncName '"' | "'" EncName "'" )
 fn EncodingDecl(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    recognize(tuple((
-        multispace1,
-        tag("encoding"),
-        Eq,
+    recognize(EncodingDecl_value)(input)
+}
+
+fn EncodingDecl_value(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    preceded(
+        tuple((multispace1, tag("encoding"), Eq)),
         alt((
             delimited(char('"'), EncName, char('"')),
             delimited(char('\''), EncName, char('\'')),
         )),
-    )))(input)
+    )(input)
 }
 #[test]
 fn test_EncodingDecl() {
@@ -859,15 +895,17 @@ fn yes_mi_no_mu(input: &[u8]) -> IResult<&[u8], &[u8]> {
     alt((tag("yes"), tag("no")))(input)
 }
 fn SDDecl(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    recognize(tuple((
-        multispace1,
-        tag("standalone"),
-        Eq,
+    recognize(SDDecl_value)(input)
+}
+
+fn SDDecl_value(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    preceded(
+        tuple((multispace1, tag("standalone"), Eq)),
         alt((
             delimited(char('"'), yes_mi_no_mu, char('"')),
             delimited(char('\''), yes_mi_no_mu, char('\'')),
         )),
-    )))(input)
+    )(input)
 }
 #[test]
 fn test_SDDecl() {
@@ -895,6 +933,47 @@ fn test_XMLDecl() {
     println!("{:?}", res);
 }
 
+pub(crate) struct XmlDeclValues<'a> {
+    pub version: &'a [u8],
+    pub encoding: Option<&'a [u8]>,
+    pub standalone: Option<&'a [u8]>,
+}
+
+pub(crate) fn XMLDecl_values(input: &[u8]) -> IResult<&[u8], XmlDeclValues<'_>> {
+    map(
+        tuple((
+            tag("<?xml"),
+            VersionInfo_value,
+            opt(EncodingDecl_value),
+            opt(SDDecl_value),
+            multispace0,
+            tag("?>"),
+        )),
+        |(_, version, encoding, standalone, _, _)| XmlDeclValues {
+            version,
+            encoding,
+            standalone,
+        },
+    )(input)
+}
+
+#[test]
+fn test_XMLDecl_values() {
+    let data = r#"<?xml version="1.0"  encoding="UTF-8" standalone='yes'?>"#.as_bytes();
+    let (_, values) = XMLDecl_values(&data).unwrap();
+    assert_eq!(values.version, b"1.0");
+    assert_eq!(values.encoding, Some(&b"UTF-8"[..]));
+    assert_eq!(values.standalone, Some(&b"yes"[..]));
+
+    let (_, values) = XMLDecl_values(b"<?xml version='1.1'?>").unwrap();
+    assert_eq!(values.version, b"1.1");
+    assert_eq!(values.encoding, None);
+    assert_eq!(values.standalone, None);
+
+    let (_, values) = XMLDecl_values(b"<?xml version='2.0'?>").unwrap();
+    assert_eq!(values.version, b"2.0");
+}
+
 // [1] document ::= prolog element Misc*
 // [22] prolog ::= XMLDecl? Misc* (doctypedecl Misc*)?
 
@@ -924,8 +1003,8 @@ fn inside_Comment_or_CDATA_single_pure(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
     // let c = unsafe { std::str::from_utf8_unchecked(&input[..width]) }.chars().next().unwrap();
@@ -1133,6 +1212,76 @@ fn test_pi() {
     );
 }
 
+// [16] PI ::= '<?' PITarget (S (Char* - (Char* '?>' Char*)))? '?>'
+// splits a PI recognized by `PI` into target and data
+pub(crate) fn PI_values(input: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
+    tuple((
+        preceded(PI_start, name),
+        alt((
+            map(PI_end, |end: &[u8]| &end[..0]),
+            delimited(
+                multispace1,
+                recognize(many0_custom_chardata(inside_PI_single)),
+                PI_end,
+            ),
+        )),
+    ))(input)
+}
+
+#[test]
+fn test_PI_values() {
+    assert_eq!(
+        PI_values(b"<?target?>"),
+        Ok((&b""[..], (&b"target"[..], &b""[..])))
+    );
+    assert_eq!(
+        PI_values(b"<?target  some data ?>"),
+        Ok((&b""[..], (&b"target"[..], &b"some data "[..])))
+    );
+    assert!(PI_values(b"<??>").is_err());
+    assert!(PI_values(b"<?target?data?>").is_err());
+}
+
+// pseudo-attribute in PI data, e.g. `href="style.css"`, returns the name and value ranges
+pub(crate) fn pseudo_attribute(input: &[u8]) -> IResult<&[u8], (Range<usize>, Range<usize>)> {
+    match preceded(
+        multispace0,
+        separated_pair(
+            name,
+            Eq,
+            alt((
+                delimited(char('"'), take_while(|c| c != b'"'), char('"')),
+                delimited(char('\''), take_while(|c| c != b'\''), char('\'')),
+            )),
+        ),
+    )(input)
+    {
+        Ok((i, (name, value))) => {
+            let name_start = input.offset(name);
+            let value_start = input.offset(value);
+            Ok((
+                i,
+                (
+                    name_start..name_start + name.len(),
+                    value_start..value_start + value.len(),
+                ),
+            ))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn test_pseudo_attribute() {
+    let data = br#" href="a.css" type='text/css'"#;
+    let (rest, (name, value)) = pseudo_attribute(data).unwrap();
+    assert_eq!(&data[name], b"href");
+    assert_eq!(&data[value], b"a.css");
+    let (_, (name, value)) = pseudo_attribute(rest).unwrap();
+    assert_eq!(&rest[name], b"type");
+    assert_eq!(&rest[value], b"text/css");
+}
+
 pub enum InsideCdata<'a> {
     Characters(&'a [u8]),
     CdataEnd,
@@ -1265,8 +1414,8 @@ fn nc_namestart_char(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
 
@@ -1299,8 +1448,8 @@ fn nc_namechar(input: &[u8]) -> IResult<&[u8], &[u8]> {
         return Err(Err::Incomplete(Needed::new(width - input.len())));
     }
 
-    let c = match std::str::from_utf8(&input[..width]).ok() {
-        Some(s) => s.chars().next().unwrap(),
+    let c = match first_char(&input[..width]) {
+        Some(c) => c,
         None => return Err(Err::Error(Error::new(input, ErrorKind::Char))),
     };
 
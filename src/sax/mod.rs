@@ -1,9 +1,16 @@
+pub mod catalog;
 mod circular;
+pub mod declaration;
+mod doctype;
 mod dtd;
+mod encoding;
 mod internal;
 pub mod parser;
+pub mod resolver;
+pub mod validation;
 
-use crate::sax::parser::convert_attribute_range;
+use crate::sax::declaration::{AttlistDecl, ElementDecl, EntityDecl, NotationDecl};
+use crate::sax::parser::{convert_attribute_range, Namespace};
 // Pull API
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -33,13 +40,44 @@ impl<'a> Iterator for Attributes<'a> {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Attribute<'a> {
+    /// Normalized value, references are resolved and whitespace characters are replaced with spaces
     pub value: &'a str,
+    /// Value between the quotes before attribute-value normalization
+    pub raw_value: &'a str,
     pub name: &'a str,
     // namespace aware
     pub local_name: &'a str,
     pub prefix: &'a str,
     pub namespace: &'a str,
+    /// `false` for a default value from an ATTLIST declaration
+    pub specified: bool,
 }
+/// A namespace declaration. `prefix` is empty for the default namespace.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct PrefixMapping<'a> {
+    pub prefix: &'a str,
+    pub namespace: &'a str,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrefixMappings<'a> {
+    index: usize,
+    list: &'a [Namespace],
+    namespace_strbuffer: &'a str,
+}
+
+impl<'a> Iterator for PrefixMappings<'a> {
+    type Item = PrefixMapping<'a>;
+    fn next(&mut self) -> Option<PrefixMapping<'a>> {
+        let ns = self.list.get(self.index)?;
+        self.index += 1;
+        Some(PrefixMapping {
+            prefix: &self.namespace_strbuffer[ns.prefix.clone()],
+            namespace: &self.namespace_strbuffer[ns.value.clone()],
+        })
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StartElement<'a> {
     pub name: &'a str,
@@ -51,10 +89,21 @@ pub struct StartElement<'a> {
     pub namespace: &'a str,
 
     range_list: &'a Vec<internal::AttributeRange>,
+    prefix_mapping_list: &'a [Namespace],
     strbuffer: &'a str,
     namespace_strbuffer: &'a str,
 }
 impl<'a> StartElement<'a> {
+    /// Prefixes that come into scope with this element, in declaration order.
+    /// For an empty element tag they also go out of scope with it.
+    pub fn prefix_mappings(&self) -> PrefixMappings<'a> {
+        PrefixMappings {
+            index: 0,
+            list: self.prefix_mapping_list,
+            namespace_strbuffer: self.namespace_strbuffer,
+        }
+    }
+
     pub fn attributes(&self) -> Attributes<'a> {
         Attributes {
             index: 0,
@@ -72,6 +121,19 @@ pub struct EndElement<'a> {
     pub local_name: &'a str,
     pub prefix: &'a str,
     pub namespace: &'a str,
+
+    prefix_mapping_list: &'a [Namespace],
+    namespace_strbuffer: &'a str,
+}
+impl<'a> EndElement<'a> {
+    /// Prefixes that go out of scope with this element.
+    pub fn prefix_mappings(&self) -> PrefixMappings<'a> {
+        PrefixMappings {
+            index: 0,
+            list: self.prefix_mapping_list,
+            namespace_strbuffer: self.namespace_strbuffer,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -80,6 +142,111 @@ pub struct Reference<'a> {
     pub resolved: Option<&'a str>,
 }
 
+/// A processing instruction, `<?target data?>`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ProcessingInstruction<'a> {
+    pub target: &'a str,
+    pub data: &'a str,
+}
+
+impl<'a> ProcessingInstruction<'a> {
+    /// Reads the data as pseudo-attributes, e.g. `href="style.css" type="text/css"`.
+    ///
+    /// Values are returned as written. Iteration stops at the first malformed pair.
+    pub fn pseudo_attributes(&self) -> PseudoAttributes<'a> {
+        PseudoAttributes { input: self.data }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct PseudoAttribute<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PseudoAttributes<'a> {
+    input: &'a str,
+}
+
+impl<'a> Iterator for PseudoAttributes<'a> {
+    type Item = PseudoAttribute<'a>;
+    fn next(&mut self) -> Option<PseudoAttribute<'a>> {
+        let input = self.input;
+        match internal::pseudo_attribute(input.as_bytes()) {
+            Ok((rest, (name, value))) => {
+                self.input = &input[input.len() - rest.len()..];
+                Some(PseudoAttribute {
+                    name: &input[name],
+                    value: &input[value],
+                })
+            }
+            Err(_) => {
+                self.input = "";
+                None
+            }
+        }
+    }
+}
+
+/// The document type declaration, e.g. `<!DOCTYPE greeting SYSTEM "hello.dtd" [ ... ]>`
+///
+/// `internal_subset` is the text between the brackets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct DocType<'a> {
+    pub name: &'a str,
+    pub public_id: Option<&'a str>,
+    pub system_id: Option<&'a str>,
+    pub internal_subset: Option<&'a str>,
+}
+
+/// The XML declaration, e.g. `<?xml version="1.0" encoding="UTF-8" standalone="yes"?>`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct XmlDecl<'a> {
+    pub version: &'a str,
+    pub encoding: Option<&'a str>,
+    pub standalone: Option<bool>,
+}
+
+/// A location in the input.
+///
+/// `offset` is the absolute byte offset in the input, also for transcoded UTF-16 input.
+/// `line` and `column` start from 1.
+/// Columns are counted in characters, `\r\n` and a lone `\r` both end a line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {} (byte offset {})",
+            self.line, self.column, self.offset
+        )
+    }
+}
+
+/// The input range an event was parsed from. `end` is exclusive.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Event<'a> {
     StartDocument,
@@ -88,6 +255,8 @@ pub enum Event<'a> {
     StartElement(StartElement<'a>),
     EndElement(EndElement<'a>),
     Characters(&'a str),
+    /// Whitespace between the children of an element declared with element content
+    IgnorableWhitespace(&'a str),
     Reference(Reference<'a>),
 
     StartComment,
@@ -98,8 +267,257 @@ pub enum Event<'a> {
     Cdata(&'a str),
     EndCdataSection,
 
-    DocumentTypeDeclaration(&'a str),
-    ProcessingInstruction(&'a str),
-    XmlDeclaration(&'a str),
+    DocumentTypeDeclaration(DocType<'a>),
+    ProcessingInstruction(ProcessingInstruction<'a>),
+    XmlDeclaration(XmlDecl<'a>),
     Whitespace(&'a str),
+
+    // reported with `options::MarkupDeclarations::Report`
+    ElementDecl(&'a ElementDecl),
+    AttlistDecl(&'a AttlistDecl),
+    EntityDecl(&'a EntityDecl),
+    NotationDecl(&'a NotationDecl),
+}
+
+impl<'a> Event<'a> {
+    /// Copies the event so it can outlive the next `read_event` call.
+    pub fn to_owned(&self) -> OwnedEvent {
+        match self {
+            Event::StartDocument => OwnedEvent::StartDocument,
+            Event::EndDocument => OwnedEvent::EndDocument,
+
+            Event::StartElement(el) => OwnedEvent::StartElement(el.into()),
+            Event::EndElement(el) => OwnedEvent::EndElement(el.into()),
+            Event::Characters(c) => OwnedEvent::Characters(c.to_string()),
+            Event::IgnorableWhitespace(c) => OwnedEvent::IgnorableWhitespace(c.to_string()),
+            Event::Reference(r) => OwnedEvent::Reference(r.into()),
+
+            Event::StartComment => OwnedEvent::StartComment,
+            Event::Comment(c) => OwnedEvent::Comment(c.to_string()),
+            Event::EndComment => OwnedEvent::EndComment,
+
+            Event::StartCdataSection => OwnedEvent::StartCdataSection,
+            Event::Cdata(c) => OwnedEvent::Cdata(c.to_string()),
+            Event::EndCdataSection => OwnedEvent::EndCdataSection,
+
+            Event::DocumentTypeDeclaration(doctype) => {
+                OwnedEvent::DocumentTypeDeclaration(doctype.into())
+            }
+            Event::ProcessingInstruction(pi) => OwnedEvent::ProcessingInstruction(pi.into()),
+            Event::XmlDeclaration(decl) => OwnedEvent::XmlDeclaration(decl.into()),
+            Event::Whitespace(c) => OwnedEvent::Whitespace(c.to_string()),
+
+            Event::ElementDecl(decl) => OwnedEvent::ElementDecl((*decl).clone()),
+            Event::AttlistDecl(decl) => OwnedEvent::AttlistDecl((*decl).clone()),
+            Event::EntityDecl(decl) => OwnedEvent::EntityDecl((*decl).clone()),
+            Event::NotationDecl(decl) => OwnedEvent::NotationDecl((*decl).clone()),
+        }
+    }
+
+    pub fn into_owned(self) -> OwnedEvent {
+        self.to_owned()
+    }
+}
+
+// Owned versions of the events
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedAttribute {
+    pub value: String,
+    pub raw_value: String,
+    pub name: String,
+    // namespace aware
+    pub local_name: String,
+    pub prefix: String,
+    pub namespace: String,
+    pub specified: bool,
+}
+
+impl<'a> From<&Attribute<'a>> for OwnedAttribute {
+    fn from(attr: &Attribute<'a>) -> Self {
+        OwnedAttribute {
+            value: attr.value.to_owned(),
+            raw_value: attr.raw_value.to_owned(),
+            name: attr.name.to_owned(),
+            local_name: attr.local_name.to_owned(),
+            prefix: attr.prefix.to_owned(),
+            namespace: attr.namespace.to_owned(),
+            specified: attr.specified,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedPrefixMapping {
+    pub prefix: String,
+    pub namespace: String,
+}
+
+impl<'a> From<PrefixMapping<'a>> for OwnedPrefixMapping {
+    fn from(mapping: PrefixMapping<'a>) -> Self {
+        OwnedPrefixMapping {
+            prefix: mapping.prefix.to_owned(),
+            namespace: mapping.namespace.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedStartElement {
+    pub name: String,
+    pub attributes: Vec<OwnedAttribute>,
+    pub prefix_mappings: Vec<OwnedPrefixMapping>,
+    pub is_empty: bool,
+    // namespace aware
+    pub local_name: String,
+    pub prefix: String,
+    pub namespace: String,
+}
+
+impl<'a> From<&StartElement<'a>> for OwnedStartElement {
+    fn from(el: &StartElement<'a>) -> Self {
+        OwnedStartElement {
+            name: el.name.to_owned(),
+            attributes: el.attributes().map(|attr| (&attr).into()).collect(),
+            prefix_mappings: el.prefix_mappings().map(|m| m.into()).collect(),
+            is_empty: el.is_empty,
+            local_name: el.local_name.to_owned(),
+            prefix: el.prefix.to_owned(),
+            namespace: el.namespace.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedEndElement {
+    pub name: String,
+    // namespace aware
+    pub local_name: String,
+    pub prefix: String,
+    pub namespace: String,
+    pub prefix_mappings: Vec<OwnedPrefixMapping>,
+}
+
+impl<'a> From<&EndElement<'a>> for OwnedEndElement {
+    fn from(el: &EndElement<'a>) -> Self {
+        OwnedEndElement {
+            name: el.name.to_owned(),
+            local_name: el.local_name.to_owned(),
+            prefix: el.prefix.to_owned(),
+            namespace: el.namespace.to_owned(),
+            prefix_mappings: el.prefix_mappings().map(|m| m.into()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedReference {
+    pub raw: String,
+    pub resolved: Option<String>,
+}
+
+impl<'a> From<&Reference<'a>> for OwnedReference {
+    fn from(reference: &Reference<'a>) -> Self {
+        OwnedReference {
+            raw: reference.raw.to_owned(),
+            resolved: reference.resolved.map(|r| r.to_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedProcessingInstruction {
+    pub target: String,
+    pub data: String,
+}
+
+impl<'a> From<&ProcessingInstruction<'a>> for OwnedProcessingInstruction {
+    fn from(pi: &ProcessingInstruction<'a>) -> Self {
+        OwnedProcessingInstruction {
+            target: pi.target.to_owned(),
+            data: pi.data.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedDocType {
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub internal_subset: Option<String>,
+}
+
+impl<'a> From<&DocType<'a>> for OwnedDocType {
+    fn from(doctype: &DocType<'a>) -> Self {
+        OwnedDocType {
+            name: doctype.name.to_owned(),
+            public_id: doctype.public_id.map(|id| id.to_owned()),
+            system_id: doctype.system_id.map(|id| id.to_owned()),
+            internal_subset: doctype.internal_subset.map(|s| s.to_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedXmlDecl {
+    pub version: String,
+    pub encoding: Option<String>,
+    pub standalone: Option<bool>,
+}
+
+impl OwnedXmlDecl {
+    pub fn as_xml_decl(&self) -> XmlDecl<'_> {
+        XmlDecl {
+            version: &self.version,
+            encoding: self.encoding.as_deref(),
+            standalone: self.standalone,
+        }
+    }
+}
+
+impl<'a> From<&XmlDecl<'a>> for OwnedXmlDecl {
+    fn from(decl: &XmlDecl<'a>) -> Self {
+        OwnedXmlDecl {
+            version: decl.version.to_owned(),
+            encoding: decl.encoding.map(|e| e.to_owned()),
+            standalone: decl.standalone,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum OwnedEvent {
+    StartDocument,
+    EndDocument,
+
+    StartElement(OwnedStartElement),
+    EndElement(OwnedEndElement),
+    Characters(String),
+    IgnorableWhitespace(String),
+    Reference(OwnedReference),
+
+    StartComment,
+    Comment(String),
+    EndComment,
+
+    StartCdataSection,
+    Cdata(String),
+    EndCdataSection,
+
+    DocumentTypeDeclaration(OwnedDocType),
+    ProcessingInstruction(OwnedProcessingInstruction),
+    XmlDeclaration(OwnedXmlDecl),
+    Whitespace(String),
+
+    ElementDecl(ElementDecl),
+    AttlistDecl(AttlistDecl),
+    EntityDecl(EntityDecl),
+    NotationDecl(NotationDecl),
+}
+
+impl<'a> From<Event<'a>> for OwnedEvent {
+    fn from(event: Event<'a>) -> Self {
+        event.into_owned()
+    }
 }
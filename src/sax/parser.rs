@@ -2,11 +2,21 @@ use nom::Offset;
 
 use crate::{
     sax as xml_sax,
+    sax::declaration::{ContentSpec, Dtd, MarkupDecl},
+    sax::doctype::{
+        add_default_attributes, normalize_attribute_value, read_dtd, resolve_reference,
+        AttributeDefaults, Entities, EntityFrame,
+    },
+    sax::dtd::doctypedecl_values,
+    sax::encoding::{DecodeError, Decoder, Encoding},
     sax::internal::{
-        content_relaxed, insidecdata, insidecomment, misc, misc_before_doctype,
-        misc_before_xmldecl, Attribute2, AttributeRange, ContentRelaxed, InsideCdata,
-        InsideComment, Misc, MiscBeforeDoctype, MiscBeforeXmlDecl, QName,
+        content_relaxed, first_char, insidecdata, insidecomment, is_xml_char_t, misc,
+        misc_before_doctype, misc_before_xmldecl, utf8_char_width, Attribute2, AttributeRange,
+        ContentRelaxed, InsideCdata, InsideComment, Misc, MiscBeforeDoctype, MiscBeforeXmlDecl,
+        PI_values, QName, STag_prefix, XMLDecl_values,
     },
+    sax::resolver::EntityResolver,
+    sax::validation::{Validator, ValidityError},
 };
 
 enum InternalSuccess<'a> {
@@ -21,7 +31,23 @@ enum InternalSuccess<'a> {
     MiscBeforeXmlDecl(MiscBeforeXmlDecl<'a>),
 }
 
+impl<'a> InternalSuccess<'a> {
+    // text that can end with a line break
+    fn text(&self) -> Option<&'a [u8]> {
+        match self {
+            InternalSuccess::ContentRelaxed(ContentRelaxed::CharData(a)) => Some(a),
+            InternalSuccess::InsideCdata(InsideCdata::Characters(a)) => Some(a),
+            InternalSuccess::InsideComment(InsideComment::Characters(a)) => Some(a),
+            InternalSuccess::Misc(Misc::Whitespace(a)) => Some(a),
+            InternalSuccess::MiscBeforeDoctype(MiscBeforeDoctype::Whitespace(a)) => Some(a),
+            InternalSuccess::MiscBeforeXmlDecl(MiscBeforeXmlDecl::Whitespace(a)) => Some(a),
+            _ => None,
+        }
+    }
+}
+
 use std::{
+    collections::HashSet,
     io::{BufRead, BufReader, Read, Write},
     ops::Range,
 };
@@ -47,10 +73,11 @@ enum ParserState {
     DocEndInsideComment,
 }
 
-struct Namespace {
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Namespace {
     level: usize,
-    prefix: Range<usize>,
-    value: Range<usize>,
+    pub(crate) prefix: Range<usize>,
+    pub(crate) value: Range<usize>,
 }
 
 pub mod options {
@@ -58,6 +85,89 @@ pub mod options {
         Enabled,
         Disabled,
     }
+
+    /// Line breaks in text, attribute values, comments and PIs.
+    pub enum LineEndings {
+        /// Translate `\r\n` and lone `\r` to `\n` as XML 1.0 §2.11 requires (default)
+        Normalize,
+        /// Keep the raw text, e.g. for round-tripping tools
+        Preserve,
+    }
+
+    /// Coalescing of adjacent text into `Characters` events.
+    pub enum TextCoalescing {
+        /// Text may be split at buffer boundaries (default)
+        Disabled,
+        /// One `Characters` event per text node, including resolved references and
+        /// optionally CDATA sections. A text node longer than `max_size` bytes is
+        /// split into events of at most `max_size` bytes. `max_size` is at least 4,
+        /// the length of a UTF-8 character, smaller values are raised to it.
+        Enabled {
+            include_cdata: bool,
+            max_size: usize,
+        },
+    }
+
+    /// How resolved character and predefined entity references are reported.
+    /// References that can't be resolved always come as `Reference` events.
+    pub enum References {
+        /// Merged into the neighbouring `Characters` event (default)
+        Merge,
+        /// Each one as a separate `Reference` event
+        Separate,
+    }
+
+    /// Limits for the expansion of entities declared in the DTD, exceeding one is an
+    /// `EntityExpansionLimit` error. The defaults stop "billion laughs" and quadratic
+    /// blowup documents early.
+    pub struct EntityLimits {
+        /// Entities referenced from replacement text, 16 levels by default
+        pub max_depth: usize,
+        /// Bytes of replacement text in the whole document, 16 MiB by default
+        pub max_expanded_size: usize,
+        /// Bytes of replacement text per byte of input, 100 by default.
+        /// It is checked once more than 1 MiB is expanded.
+        pub max_amplification: usize,
+    }
+
+    impl Default for EntityLimits {
+        fn default() -> Self {
+            EntityLimits {
+                max_depth: 16,
+                max_expanded_size: 16 * 1024 * 1024,
+                max_amplification: 100,
+            }
+        }
+    }
+
+    /// Markup declarations of the internal and the external subset.
+    pub enum MarkupDeclarations {
+        /// Only the `DocumentTypeDeclaration` event (default)
+        Skip,
+        /// One event per element, attribute list, entity and notation declaration
+        /// after the `DocumentTypeDeclaration` event
+        Report,
+    }
+
+    /// Validation against the DTD, XML 1.0 §5.1. Validity errors don't stop parsing,
+    /// they are collected in [Parser::validity_errors](super::Parser::validity_errors).
+    pub enum Validation {
+        /// Only well-formedness is checked (default). Declared attribute defaults are
+        /// still added, but a `#FIXED` attribute with a different value is accepted.
+        Disabled,
+        /// Element content, attribute values, required and `#FIXED` attributes and
+        /// IDREFs are checked against the declarations
+        Enabled,
+    }
+
+    /// Whitespace between the children of an element that is declared with element
+    /// content, like `<!ELEMENT doc (e)>`, XML 1.0 §2.10.
+    pub enum IgnorableWhitespace {
+        /// As `IgnorableWhitespace` events instead of `Characters` (default)
+        Report,
+        /// Not reported at all
+        Skip,
+    }
 }
 
 pub struct ParserBuilder<R: Read> {
@@ -65,11 +175,17 @@ pub struct ParserBuilder<R: Read> {
 }
 impl<R: Read> ParserBuilder<R> {
     pub fn from_reader(reader: R) -> ParserBuilder<R> {
+        // the xml prefix is bound by definition, it is never removed
+        let mut namespace_strbuffer = String::new();
+        let xml_ns = push_ns_values_get_ns(&mut namespace_strbuffer, "xml", XML_NAMESPACE, 0);
+
         ParserBuilder {
             parser: Parser {
                 state: ParserState::Initial,
                 bufreader: BufReader::with_capacity(8 * 1024, reader),
                 offset: 0,
+                cursor: Cursor::default(),
+                event_end: Cursor::default(),
 
                 buffer3: circular::Buffer::with_capacity(16 * 1024),
                 strbuffer: String::new(),
@@ -79,10 +195,25 @@ impl<R: Read> ParserBuilder<R> {
                 element_strbuffer: String::new(),
 
                 is_namespace_aware: true,
-                namespace_list: Vec::with_capacity(10),
-                namespace_strbuffer: String::new(),
+                namespace_list: vec![xml_ns],
+                namespace_strbuffer,
 
                 attribute_list: Vec::with_capacity(5),
+                line_endings: LineEndings::new(true),
+                merge_references: true,
+                coalescing: None,
+                xml_declaration: None,
+                report_declarations: false,
+                dtd: None,
+                declaration_index: 0,
+                entities: Entities::new(),
+                entity_frames: Vec::new(),
+                attribute_defaults: AttributeDefaults::default(),
+                validator: None,
+                decoder: Decoder::new(),
+                redecode: false,
+                element_content: HashSet::new(),
+                skip_ignorable_whitespace: false,
             },
         }
     }
@@ -103,6 +234,126 @@ impl<R: Read> ParserBuilder<R> {
         self
     }
 
+    pub fn line_endings(mut self, line_endings: options::LineEndings) -> ParserBuilder<R> {
+        match line_endings {
+            options::LineEndings::Normalize => {
+                self.parser.line_endings.normalize = true;
+            }
+            options::LineEndings::Preserve => {
+                self.parser.line_endings.normalize = false;
+            }
+        }
+
+        self
+    }
+
+    pub fn references(mut self, references: options::References) -> ParserBuilder<R> {
+        match references {
+            options::References::Merge => {
+                self.parser.merge_references = true;
+            }
+            options::References::Separate => {
+                self.parser.merge_references = false;
+            }
+        }
+
+        self
+    }
+
+    pub fn text_coalescing(mut self, coalescing: options::TextCoalescing) -> ParserBuilder<R> {
+        match coalescing {
+            options::TextCoalescing::Disabled => {
+                self.parser.coalescing = None;
+            }
+            options::TextCoalescing::Enabled {
+                include_cdata,
+                max_size,
+            } => {
+                self.parser.coalescing = Some(Coalescing {
+                    cdata: include_cdata,
+                    // a character always fits
+                    max_size: max_size.max(4),
+                });
+            }
+        }
+
+        self
+    }
+
+    pub fn markup_declarations(
+        mut self,
+        declarations: options::MarkupDeclarations,
+    ) -> ParserBuilder<R> {
+        match declarations {
+            options::MarkupDeclarations::Skip => {
+                self.parser.report_declarations = false;
+            }
+            options::MarkupDeclarations::Report => {
+                self.parser.report_declarations = true;
+            }
+        }
+
+        self
+    }
+
+    pub fn validation(mut self, validation: options::Validation) -> ParserBuilder<R> {
+        match validation {
+            options::Validation::Disabled => {
+                self.parser.validator = None;
+            }
+            options::Validation::Enabled => {
+                self.parser.validator = Some(Validator::new());
+            }
+        }
+
+        self
+    }
+
+    pub fn ignorable_whitespace(
+        mut self,
+        whitespace: options::IgnorableWhitespace,
+    ) -> ParserBuilder<R> {
+        match whitespace {
+            options::IgnorableWhitespace::Report => {
+                self.parser.skip_ignorable_whitespace = false;
+            }
+            options::IgnorableWhitespace::Skip => {
+                self.parser.skip_ignorable_whitespace = true;
+            }
+        }
+
+        self
+    }
+
+    pub fn entity_limits(mut self, limits: options::EntityLimits) -> ParserBuilder<R> {
+        self.parser.entities.limits = limits;
+
+        self
+    }
+
+    /// Resolver for the external subset and external parsed entities,
+    /// [DenyResolver](crate::sax::resolver::DenyResolver) by default.
+    pub fn entity_resolver(mut self, resolver: impl EntityResolver + 'static) -> ParserBuilder<R> {
+        self.parser.entities.resolver = Box::new(resolver);
+
+        self
+    }
+
+    /// URI of the document, relative system identifiers are resolved against it.
+    pub fn base_uri(mut self, base_uri: &str) -> ParserBuilder<R> {
+        self.parser.entities.base_uri = Some(base_uri.to_owned());
+
+        self
+    }
+
+    /// Encoding of the input like `ISO-8859-1`, instead of the one detected from the
+    /// byte order mark and the encoding declaration, which is not checked then.
+    pub fn encoding(mut self, encoding: &str) -> ParserBuilder<R> {
+        self.parser.decoder.force(encoding);
+
+        self
+    }
+
     pub fn build(self) -> Parser<R> {
         self.parser
     }
@@ -115,6 +366,8 @@ pub struct Parser<R: Read> {
 
     strbuffer: String,
     offset: usize,
+    cursor: Cursor,
+    event_end: Cursor,
 
     // document_complete: bool, //if element_level reaches 0 again , we control this via state
     element_level: usize,
@@ -126,6 +379,82 @@ pub struct Parser<R: Read> {
     namespace_list: Vec<Namespace>,
 
     attribute_list: Vec<AttributeRange>,
+    line_endings: LineEndings,
+    merge_references: bool,
+    coalescing: Option<Coalescing>,
+    xml_declaration: Option<xml_sax::OwnedXmlDecl>,
+
+    report_declarations: bool,
+    // declarations of the DTD are reported one by one after the DOCTYPE
+    dtd: Option<Dtd>,
+    declaration_index: usize,
+
+    entities: Entities,
+    // innermost last
+    entity_frames: Vec<EntityFrame>,
+    attribute_defaults: AttributeDefaults,
+    validator: Option<Validator>,
+    decoder: Decoder,
+    // the buffered input after the XML declaration is in the declared encoding
+    redecode: bool,
+    // elements declared with element content
+    element_content: HashSet<String>,
+    skip_ignorable_whitespace: bool,
+}
+
+struct Coalescing {
+    cdata: bool,
+    max_size: usize,
+}
+
+/// Iterator returned by [`Parser::into_owned_events`].
+pub struct OwnedEvents<R: Read> {
+    parser: Parser<R>,
+    finished: bool,
+}
+
+impl<R: Read> OwnedEvents<R> {
+    pub fn parser(&self) -> &Parser<R> {
+        &self.parser
+    }
+}
+
+impl<R: Read> Iterator for OwnedEvents<R> {
+    type Item = SaxResult<xml_sax::OwnedEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.parser.read_event() {
+            Ok(event) => {
+                if event == xml_sax::Event::EndDocument {
+                    self.finished = true;
+                }
+                Some(Ok(event.into_owned()))
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// Characters event that is built from text and resolved references
+struct MergedCharacters {
+    start: xml_sax::Position,
+    after_reference: bool,
+    // CDATA sections are not ignorable whitespace, the validator sees them
+    cdata: bool,
+    // nor are character references, XML 1.0 §3.2.1
+    references: bool,
+}
+
+impl MergedCharacters {
+    fn may_be_ignorable(&self) -> bool {
+        !self.cdata && !self.references
+    }
 }
 
 pub(crate) fn convert_attribute_range<'a>(
@@ -135,18 +464,56 @@ pub(crate) fn convert_attribute_range<'a>(
 ) -> Attribute<'a> {
     Attribute {
         value: &strbuffer[range.value],
+        raw_value: &strbuffer[range.raw_value],
         name: &strbuffer[range.name],
         local_name: &strbuffer[range.local_name],
         prefix: &strbuffer[range.prefix],
         namespace: &namespace_strbuffer[range.namespace],
+        specified: range.specified,
     }
 }
 
+// updates the frames after `consumed` bytes of an event,
+// the replacement text must be `content` (Parsed Entity WFC)
+fn leave_entities(
+    frames: &mut Vec<EntityFrame>,
+    consumed: usize,
+    is_text: bool,
+    state: ParserState,
+    element_level: usize,
+) -> Result<(), error::ErrorKind> {
+    while let Some(frame) = frames.last() {
+        if frame.end > consumed {
+            if element_level < frame.element_level {
+                return Err(error::ErrorKind::UnbalancedEntity {
+                    name: frame.name.clone(),
+                });
+            }
+            break;
+        }
+        // only text can go on after the end of the replacement text
+        if (frame.end < consumed && !is_text)
+            || state != ParserState::Content
+            || element_level != frame.element_level
+        {
+            return Err(error::ErrorKind::UnbalancedEntity {
+                name: frame.name.clone(),
+            });
+        }
+        frames.pop();
+    }
+    for frame in frames.iter_mut() {
+        frame.end -= consumed;
+    }
+    Ok(())
+}
+
 fn convert_start_element_name_and_add_attributes<'a>(
     strbuffer: &'a mut String,
     event1: crate::sax::internal::StartElement,
     attribute_list: &'a mut Vec<AttributeRange>,
-) -> SaxResult<Range<usize>> {
+    context: &mut ConverterContext,
+) -> InternalResult<Range<usize>> {
     attribute_list.clear();
 
     let start = strbuffer.len();
@@ -156,13 +523,16 @@ fn convert_start_element_name_and_add_attributes<'a>(
 
     // let mut attributes2: Vec<SAXAttribute2> = vec![];
 
-    let start = strbuffer.len();
-    let size = event1.attributes_chunk.len();
     let attributes_chunk = unsafe { std::str::from_utf8_unchecked(event1.attributes_chunk) };
-    strbuffer.push_str(attributes_chunk);
+    // attribute values are parsed from the normalized copy
+    let attributes_range = if context.line_endings.normalize {
+        LineEndings::new(true).push_str_get_range(strbuffer, attributes_chunk)
+    } else {
+        push_str_get_range(strbuffer, attributes_chunk)
+    };
 
-    let mut inp = strbuffer[start..start + size].as_bytes();
-    let mut offset1: usize = start;
+    let mut inp = strbuffer[attributes_range.clone()].as_bytes();
+    let mut offset1: usize = attributes_range.start;
     //parse key,value and how many attributes.
     loop {
         if inp.len() == 0 {
@@ -177,6 +547,7 @@ fn convert_start_element_name_and_add_attributes<'a>(
                     (attr_range.name.start + offset1)..(attr_range.name.end + offset1);
                 attr_range.value =
                     (attr_range.value.start + offset1)..(attr_range.value.end + offset1);
+                attr_range.raw_value = attr_range.value.clone();
 
                 offset1 += inp.offset(remainder);
                 inp = remainder;
@@ -184,9 +555,45 @@ fn convert_start_element_name_and_add_attributes<'a>(
                 attribute_list.push(attr_range)
             }
             Err(_e) => {
-                return Err(error::Error::Parsing(format!(
-                    "Error while parsing attributes.",
-                )))
+                return Err(error::InternalError::Parsing(
+                    error::ErrorKind::MalformedAttribute {
+                        element: event1.name.to_owned(),
+                        found: snippet(inp),
+                    },
+                ))
+            }
+        }
+    }
+
+    // Unique Att Spec WFC
+    if let Some((_, second)) = find_duplicate(attribute_list.len(), |i| {
+        Some(&strbuffer[attribute_list[i].name.clone()])
+    }) {
+        return Err(error::InternalError::Parsing(
+            error::ErrorKind::DuplicateAttribute {
+                element: event1.name.to_owned(),
+                name: strbuffer[attribute_list[second].name.clone()].to_owned(),
+            },
+        ));
+    }
+
+    for attr in attribute_list.iter_mut() {
+        attr.value = normalize_attribute_value(strbuffer, attr.raw_value.clone(), context.entities)
+            .map_err(error::InternalError::Parsing)?;
+    }
+
+    if let Some(definitions) = context.attribute_defaults.elements.get(event1.name) {
+        let mismatches = add_default_attributes(
+            strbuffer,
+            attribute_list,
+            event1.name,
+            definitions,
+            context.entities,
+        )
+        .map_err(error::InternalError::Parsing)?;
+        if let Some(validator) = &mut context.validator {
+            for kind in mismatches {
+                validator.pending_error(kind);
             }
         }
     }
@@ -194,6 +601,55 @@ fn convert_start_element_name_and_add_attributes<'a>(
     Ok(element_name_range)
 }
 
+// pairwise comparison is cheaper than hashing for the usual few attributes
+const PAIRWISE_DUPLICATE_CHECK_LIMIT: usize = 8;
+
+// returns the indexes of the first repeated key, `None` keys are skipped
+fn find_duplicate<K: Eq + std::hash::Hash>(
+    len: usize,
+    key: impl Fn(usize) -> Option<K>,
+) -> Option<(usize, usize)> {
+    if len <= PAIRWISE_DUPLICATE_CHECK_LIMIT {
+        for i in 0..len {
+            let Some(first) = key(i) else { continue };
+            for j in (i + 1)..len {
+                if key(j).as_ref() == Some(&first) {
+                    return Some((i, j));
+                }
+            }
+        }
+        return None;
+    }
+
+    let mut seen = std::collections::HashMap::with_capacity(len);
+    for i in 0..len {
+        if let Some(k) = key(i) {
+            if let Some(&first) = seen.get(&k) {
+                return Some((first, i));
+            }
+            seen.insert(k, i);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_find_duplicate() {
+    let few = ["a", "b", "c", "b"];
+    assert_eq!(find_duplicate(few.len(), |i| Some(few[i])), Some((1, 3)));
+    assert_eq!(
+        find_duplicate(few.len(), |i| if i == 1 { None } else { Some(few[i]) }),
+        None
+    );
+
+    let many: Vec<String> = (0..20).chain([7]).map(|i| i.to_string()).collect();
+    assert_eq!(
+        find_duplicate(many.len(), |i| Some(&many[i])),
+        Some((7, 20))
+    );
+    assert_eq!(find_duplicate(many.len() - 1, |i| Some(&many[i])), None);
+}
+
 struct ElementRange {
     prefix_range: Range<usize>,
     local_name_range: Range<usize>,
@@ -209,7 +665,7 @@ fn parse_start_element(
     attribute_list: &mut Vec<AttributeRange>,
     namespace_strbuffer: &mut String,
     namespace_list: &mut Vec<Namespace>,
-) -> SaxResult<ElementRange> {
+) -> InternalResult<ElementRange> {
     let start_element_name = &strbuffer[start_element_name_range];
 
     // let mut element_local_name = "";
@@ -232,6 +688,7 @@ fn parse_start_element(
 
                     if qname.prefix == "" && qname.local_name == "xmlns" {
                         //set default namespace
+                        check_namespace_declaration("", &strbuffer[attr.value.clone()])?;
                         let ns = push_ns_values_get_ns(
                             namespace_strbuffer,
                             "",
@@ -244,6 +701,7 @@ fn parse_start_element(
                     if qname.prefix == "xmlns" {
                         //set prefixed namespace
                         let prefix = qname.local_name;
+                        check_namespace_declaration(prefix, &strbuffer[attr.value.clone()])?;
                         let ns = push_ns_values_get_ns(
                             namespace_strbuffer,
                             prefix,
@@ -268,10 +726,11 @@ fn parse_start_element(
                     // attr.local_name = &strbuffer[range_local_name];
                 }
                 Err(_e) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Attribute does not conform to QName spec: {}",
-                        &strbuffer[attr.name.clone()]
-                    )))
+                    return Err(error::InternalError::Parsing(
+                        error::ErrorKind::InvalidQName {
+                            name: strbuffer[attr.name.clone()].to_owned(),
+                        },
+                    ))
                 }
             }
         }
@@ -288,19 +747,52 @@ fn parse_start_element(
             }) {
                 Some(ns) => attr.namespace = ns.value.clone(),
                 None => {
-                    return Err(error::Error::Parsing(format!(
-                        "Namespace not found for prefix: {} , attribute: {} , element: {}",
-                        &strbuffer[attr.prefix.clone()],
-                        &strbuffer[attr.name.clone()],
-                        start_element_name
-                    )))
+                    return Err(error::InternalError::Parsing(
+                        error::ErrorKind::UnboundPrefix {
+                            prefix: strbuffer[attr.prefix.clone()].to_owned(),
+                            name: strbuffer[attr.name.clone()].to_owned(),
+                        },
+                    ))
                 }
             }
         }
 
+        // Namespaces in XML §6.3, no two attributes with the same expanded name
+        if let Some((first, second)) = find_duplicate(attribute_list.len(), |i| {
+            let attr = &attribute_list[i];
+            let namespace = &namespace_strbuffer[attr.namespace.clone()];
+            if namespace.is_empty() {
+                None
+            } else {
+                Some((namespace, &strbuffer[attr.local_name.clone()]))
+            }
+        }) {
+            let first = &attribute_list[first];
+            let second = &attribute_list[second];
+            return Err(error::InternalError::Parsing(
+                error::ErrorKind::DuplicateExpandedAttribute {
+                    element: start_element_name.to_owned(),
+                    first: strbuffer[first.name.clone()].to_owned(),
+                    second: strbuffer[second.name.clone()].to_owned(),
+                    expanded_name: format!(
+                        "{{{}}}{}",
+                        &namespace_strbuffer[second.namespace.clone()],
+                        &strbuffer[second.local_name.clone()]
+                    ),
+                },
+            ));
+        }
+
         match QName(start_element_name.as_bytes()) {
             Ok(qres) => {
                 let qname = qres.1;
+                if qname.prefix == "xmlns" {
+                    return Err(error::InternalError::Parsing(
+                        error::ErrorKind::XmlnsPrefixedElement {
+                            name: start_element_name.to_owned(),
+                        },
+                    ));
+                }
                 // element_local_name = qname.local_name;
                 // element_prefix = qname.prefix;
                 local_name_range = qname.local_name_range;
@@ -315,19 +807,22 @@ fn parse_start_element(
                         if &strbuffer[prefix_range.clone()] == "" {
                             //it is fine
                         } else {
-                            return Err(error::Error::Parsing(format!(
-                                "Namespace prefix not found for element: {}",
-                                start_element_name
-                            )));
+                            return Err(error::InternalError::Parsing(
+                                error::ErrorKind::UnboundPrefix {
+                                    prefix: strbuffer[prefix_range.clone()].to_owned(),
+                                    name: start_element_name.to_owned(),
+                                },
+                            ));
                         }
                     }
                 }
             }
             Err(_e) => {
-                return Err(error::Error::Parsing(format!(
-                    "Element name does not conform to QName spec: {}",
-                    start_element_name
-                )))
+                return Err(error::InternalError::Parsing(
+                    error::ErrorKind::InvalidQName {
+                        name: start_element_name.to_owned(),
+                    },
+                ))
             }
         }
     }
@@ -339,7 +834,153 @@ fn parse_start_element(
     })
 }
 
-fn push_str_get_range(strbuffer: &mut String, addition: &str) -> Range<usize> {
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+// Namespaces in XML §3 Namespace constraints, an empty prefix is the default namespace
+fn check_namespace_declaration(prefix: &str, value: &str) -> InternalResult<()> {
+    let kind = if prefix == "xml" {
+        if value == XML_NAMESPACE {
+            return Ok(());
+        }
+        error::ErrorKind::XmlPrefixRebound {
+            namespace: value.to_owned(),
+        }
+    } else if prefix == "xmlns" {
+        error::ErrorKind::XmlnsPrefixDeclared
+    } else if value == XML_NAMESPACE || value == XMLNS_NAMESPACE {
+        error::ErrorKind::ReservedNamespace {
+            prefix: prefix.to_owned(),
+            namespace: value.to_owned(),
+        }
+    } else if !prefix.is_empty() && value.is_empty() {
+        // undeclaring a prefix is only allowed in XML 1.1
+        error::ErrorKind::EmptyNamespace {
+            prefix: prefix.to_owned(),
+        }
+    } else {
+        return Ok(());
+    };
+    Err(error::InternalError::Parsing(kind))
+}
+
+// pushes the PI and returns the target and data ranges
+fn push_processing_instruction(
+    strbuffer: &mut String,
+    line_endings: &mut LineEndings,
+    pi: &[u8],
+) -> InternalResult<(Range<usize>, Range<usize>)> {
+    let str = unsafe { std::str::from_utf8_unchecked(pi) };
+    let range = line_endings.push_str_get_range(strbuffer, str);
+
+    let pi = strbuffer[range.clone()].as_bytes();
+    let (target, data) = match PI_values(pi) {
+        Ok((_, values)) => values,
+        Err(_) => {
+            return Err(error::InternalError::Parsing(syntax_error(
+                "processing instruction target",
+                pi,
+            )))
+        }
+    };
+    // the XML declaration is recognized before, it can't appear as a PI
+    if target.eq_ignore_ascii_case(b"xml") {
+        return Err(error::InternalError::Parsing(
+            error::ErrorKind::ReservedPiTarget {
+                target: String::from_utf8_lossy(target).into_owned(),
+            },
+        ));
+    }
+
+    let target_start = range.start + pi.offset(target);
+    let data_start = range.start + pi.offset(data);
+    Ok((
+        target_start..target_start + target.len(),
+        data_start..data_start + data.len(),
+    ))
+}
+
+struct DocTypeRanges {
+    name: Range<usize>,
+    public_id: Option<Range<usize>>,
+    system_id: Option<Range<usize>>,
+    internal_subset: Option<Range<usize>>,
+}
+
+impl DocTypeRanges {
+    fn convert(self, strbuffer: &str) -> xml_sax::DocType<'_> {
+        xml_sax::DocType {
+            name: &strbuffer[self.name],
+            public_id: self.public_id.map(|r| &strbuffer[r]),
+            system_id: self.system_id.map(|r| &strbuffer[r]),
+            internal_subset: self.internal_subset.map(|r| &strbuffer[r]),
+        }
+    }
+}
+
+// pushes the doctypedecl and returns the ranges of its fields
+fn push_doctype(
+    strbuffer: &mut String,
+    line_endings: &mut LineEndings,
+    doctype: &[u8],
+) -> InternalResult<DocTypeRanges> {
+    let str = unsafe { std::str::from_utf8_unchecked(doctype) };
+    let range = line_endings.push_str_get_range(strbuffer, str);
+
+    let doctype = strbuffer[range.clone()].as_bytes();
+    let values = match doctypedecl_values(doctype) {
+        Ok((_, values)) => values,
+        Err(_) => {
+            return Err(error::InternalError::Parsing(syntax_error(
+                "document type declaration",
+                doctype,
+            )))
+        }
+    };
+    let get_range = |value: &[u8]| {
+        let start = range.start + doctype.offset(value);
+        start..start + value.len()
+    };
+
+    Ok(DocTypeRanges {
+        name: get_range(values.name),
+        public_id: values.public_id.map(get_range),
+        system_id: values.system_id.map(get_range),
+        internal_subset: values.internal_subset.map(get_range),
+    })
+}
+
+// whitespace directly in an element declared with element content
+fn is_ignorable_whitespace(
+    element_content: &HashSet<String>,
+    element_list: &[Range<usize>],
+    element_strbuffer: &str,
+    text: &[u8],
+) -> bool {
+    let Some(range) = element_list.last() else {
+        return false;
+    };
+    element_content.contains(&element_strbuffer[range.clone()])
+        && text
+            .iter()
+            .all(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n'))
+}
+
+// names of the elements declared with element content, the first declaration binds
+fn element_content(dtd: &Dtd) -> HashSet<String> {
+    let mut declared = HashSet::new();
+    let mut names = HashSet::new();
+    for decl in &dtd.declarations {
+        if let MarkupDecl::Element(el) = decl {
+            if declared.insert(&el.name) && matches!(el.content_spec, ContentSpec::Children(_)) {
+                names.insert(el.name.clone());
+            }
+        }
+    }
+    names
+}
+
+pub(crate) fn push_str_get_range(strbuffer: &mut String, addition: &str) -> Range<usize> {
     let start = strbuffer.len();
     let size = addition.len();
     let range = Range {
@@ -350,6 +991,65 @@ fn push_str_get_range(strbuffer: &mut String, addition: &str) -> Range<usize> {
     range
 }
 
+// end-of-line handling, XML 1.0 §2.11
+struct LineEndings {
+    normalize: bool,
+    // the last text ended with `\r`, a `\n` at the beginning of the next text belongs to it
+    after_cr: bool,
+}
+
+impl LineEndings {
+    fn new(normalize: bool) -> LineEndings {
+        LineEndings {
+            normalize,
+            after_cr: false,
+        }
+    }
+
+    // pushes the text with `\r\n` and lone `\r` translated to `\n`
+    fn push_str_get_range(&mut self, strbuffer: &mut String, addition: &str) -> Range<usize> {
+        if !self.normalize {
+            return push_str_get_range(strbuffer, addition);
+        }
+
+        let start = strbuffer.len();
+        let mut rest = addition;
+        if self.after_cr && rest.starts_with('\n') {
+            rest = &rest[1..];
+        }
+        self.after_cr = rest.ends_with('\r');
+
+        while let Some(pos) = rest.find('\r') {
+            strbuffer.push_str(&rest[..pos]);
+            strbuffer.push('\n');
+            rest = &rest[pos + 1..];
+            if rest.starts_with('\n') {
+                rest = &rest[1..];
+            }
+        }
+        strbuffer.push_str(rest);
+
+        start..strbuffer.len()
+    }
+}
+
+#[test]
+fn test_line_endings() {
+    let mut strbuffer = String::new();
+    let mut line_endings = LineEndings::new(true);
+
+    let range = line_endings.push_str_get_range(&mut strbuffer, "a\r\nb\rc\r\r\nd\r");
+    assert_eq!(&strbuffer[range], "a\nb\nc\n\nd\n");
+    let range = line_endings.push_str_get_range(&mut strbuffer, "\ne");
+    assert_eq!(&strbuffer[range], "e");
+    let range = line_endings.push_str_get_range(&mut strbuffer, "\nf");
+    assert_eq!(&strbuffer[range], "\nf");
+
+    let mut line_endings = LineEndings::new(false);
+    let range = line_endings.push_str_get_range(&mut strbuffer, "a\r\nb\r");
+    assert_eq!(&strbuffer[range], "a\r\nb\r");
+}
+
 fn push_ns_values_get_ns(
     namespace_strbuffer: &mut String,
     prefix: &str,
@@ -366,21 +1066,286 @@ fn push_ns_values_get_ns(
 }
 
 pub type SaxResult<T> = Result<T, error::Error>;
+type InternalResult<T> = Result<T, error::InternalError>;
 
-mod error {
+pub mod error {
     use thiserror::Error;
+
+    use crate::sax::Position;
+
     #[derive(Debug, Error)]
     pub enum Error {
-        #[error(transparent)]
-        Io(#[from] std::io::Error),
+        #[error("{source} at {position}")]
+        Io {
+            source: std::io::Error,
+            position: Position,
+        },
+
+        #[error("SAX Parsing Err: {kind} at {position}")]
+        Parsing { kind: ErrorKind, position: Position },
+
+        #[error("SAX Parsing Err: Unexpected EOF at {position}")]
+        UnexpectedEof { position: Position },
+    }
+
+    impl Error {
+        /// Where the event that failed to parse starts.
+        pub fn position(&self) -> Position {
+            match self {
+                Error::Io { position, .. } => *position,
+                Error::Parsing { position, .. } => *position,
+                Error::UnexpectedEof { position } => *position,
+            }
+        }
+
+        /// The well-formedness problem, if this is a parsing error.
+        pub fn kind(&self) -> Option<&ErrorKind> {
+            match self {
+                Error::Parsing { kind, .. } => Some(kind),
+                _ => None,
+            }
+        }
+    }
+
+    /// Reasons why a document is not well-formed.
+    ///
+    /// `found` fields hold the beginning of the offending input, truncated to 50 characters.
+    #[derive(Clone, Debug, Eq, PartialEq, Error)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        #[error("Expected {expected}, found: {found}")]
+        Syntax {
+            expected: &'static str,
+            found: String,
+        },
+
+        #[error("Expected closing tag: {expected} ,found: {found}")]
+        MismatchedEndTag { expected: String, found: String },
+
+        #[error("No starting tag for: {name}")]
+        UnmatchedEndTag { name: String },
+
+        #[error("Elements are not closed at the end of the document: {}", names.join(", "))]
+        UnclosedElements { names: Vec<String> },
+
+        #[error("Document has no root element")]
+        NoRootElement,
+
+        #[error("Unexpected entity/content at the end of the document, found: {found}")]
+        ContentAfterRoot { found: String },
+
+        #[error("Namespace not found for prefix: {prefix} , name: {name}")]
+        UnboundPrefix { prefix: String, name: String },
+
+        #[error("Name does not conform to QName spec: {name}")]
+        InvalidQName { name: String },
+
+        #[error("Malformed attribute in element: {element} , found: {found}")]
+        MalformedAttribute { element: String, found: String },
+
+        #[error("XML version is not supported: {version}")]
+        UnsupportedVersion { version: String },
+
+        #[error("Encoding is not supported: {encoding}")]
+        UnsupportedEncoding { encoding: String },
+
+        #[error("Encoding declaration doesn't match the input: {declared} , detected: {detected}")]
+        EncodingMismatch {
+            declared: String,
+            detected: &'static str,
+        },
+
+        #[error("Input is not valid {encoding}")]
+        InvalidEncoding { encoding: &'static str },
+
+        #[error("Processing instruction target is reserved: {target}")]
+        ReservedPiTarget { target: String },
+
+        #[error("Prefix xml can't be bound to another namespace: {namespace}")]
+        XmlPrefixRebound { namespace: String },
+
+        #[error("Prefix xmlns can't be declared")]
+        XmlnsPrefixDeclared,
+
+        #[error(
+            "Reserved namespace can't be bound to prefix: {prefix:?} , namespace: {namespace}"
+        )]
+        ReservedNamespace { prefix: String, namespace: String },
+
+        #[error("Prefix can't be bound to an empty namespace: {prefix}")]
+        EmptyNamespace { prefix: String },
+
+        #[error("Element name can't have the prefix xmlns: {name}")]
+        XmlnsPrefixedElement { name: String },
+
+        #[error("Attribute is repeated in element: {element} , name: {name}")]
+        DuplicateAttribute { element: String, name: String },
+
+        #[error("Attributes have the same expanded name in element: {element} , names: {first}, {second} , expanded name: {expanded_name}")]
+        DuplicateExpandedAttribute {
+            element: String,
+            first: String,
+            second: String,
+            expanded_name: String,
+        },
+
+        #[error("Entity is not declared: {name}")]
+        UndeclaredEntity { name: String },
+
+        #[error("Entity references itself: {name}")]
+        RecursiveEntity { name: String },
+
+        #[error("Unparsed entity can't be referenced: {name}")]
+        UnparsedEntityReference { name: String },
 
-        // Generic
-        #[error("SAX Parsing Err: {0}")]
-        Parsing(String),
+        #[error("External entity can't be referenced in an attribute value: {name}")]
+        ExternalEntityInAttribute { name: String },
 
-        #[error("SAX Parsing Err: Unexpected EOF")]
+        #[error("Entity referenced in an attribute value contains '<': {name}")]
+        LessThanInAttributeValue { name: String },
+
+        #[error("Replacement text of entity is not balanced content: {name}")]
+        UnbalancedEntity { name: String },
+
+        #[error("External entity can't be loaded: {system_id} , reason: {reason}")]
+        ExternalEntity { system_id: String, reason: String },
+
+        #[error("Entity expansion limit exceeded, {limit}: {name}")]
+        EntityExpansionLimit { name: String, limit: &'static str },
+
+        #[error("Character is not allowed in XML: {character:?}")]
+        InvalidCharacter { character: char },
+
+        #[error("Input is not valid UTF-8")]
+        InvalidUtf8,
+    }
+
+    // parsing steps don't know where they are in the input, `read_event` attaches the position
+    #[derive(Debug)]
+    pub(crate) enum InternalError {
+        Parsing(ErrorKind),
         UnexpectedEof,
     }
+
+    impl InternalError {
+        pub(crate) fn at(self, position: Position) -> Error {
+            match self {
+                InternalError::Parsing(kind) => Error::Parsing { kind, position },
+                InternalError::UnexpectedEof => Error::UnexpectedEof { position },
+            }
+        }
+    }
+}
+
+// beginning of the input for error messages
+fn snippet(data: &[u8]) -> String {
+    let ending = String::from_utf8_lossy(data);
+    match ending.char_indices().nth(50) {
+        None => ending.into_owned(),
+        Some((idx, _)) => ending[..idx].to_owned(),
+    }
+}
+
+// finds out why the input couldn't be parsed as content
+fn content_error(data: &[u8]) -> error::ErrorKind {
+    if let Some(kind) = character_error(data) {
+        return kind;
+    }
+    if let Ok((remainder, name)) = STag_prefix(data) {
+        if !remainder.starts_with(b">") && !remainder.starts_with(b"/>") {
+            return error::ErrorKind::MalformedAttribute {
+                element: String::from_utf8_lossy(name).into_owned(),
+                found: snippet(remainder),
+            };
+        }
+    }
+    error::ErrorKind::Syntax {
+        expected: "one of (CharData | element | Reference | CDSect | PI | Comment)",
+        found: snippet(data),
+    }
+}
+
+// `cursor` is at the start of the input that `decoder` failed on
+fn decode_error(err: DecodeError, cursor: Cursor, decoder: &Decoder) -> error::Error {
+    let mut position = cursor.advance(decoder.decoded()).position;
+    position.offset = err.offset;
+    error::Error::Parsing {
+        kind: err.kind,
+        position,
+    }
+}
+
+pub(crate) fn syntax_error(expected: &'static str, data: &[u8]) -> error::ErrorKind {
+    character_error(data).unwrap_or_else(|| error::ErrorKind::Syntax {
+        expected,
+        found: snippet(data),
+    })
+}
+
+// checks the first character of the input
+fn character_error(data: &[u8]) -> Option<error::ErrorKind> {
+    let width = match data.first() {
+        Some(b) => utf8_char_width(*b),
+        None => return None,
+    };
+    if width == 0 {
+        return Some(error::ErrorKind::InvalidUtf8);
+    }
+    if data.len() < width {
+        return None;
+    }
+    match first_char(&data[..width]) {
+        Some(character) if is_xml_char_t(character) => None,
+        Some(character) => Some(error::ErrorKind::InvalidCharacter { character }),
+        None => Some(error::ErrorKind::InvalidUtf8),
+    }
+}
+
+// tracks the position of the first unconsumed byte
+#[derive(Clone, Copy, Debug, Default)]
+struct Cursor {
+    position: xml_sax::Position,
+    // offsets are counted in bytes of the input
+    encoding: Encoding,
+    // `\n` right after `\r` doesn't start a new line, the pair can be split between reads
+    after_cr: bool,
+}
+
+impl Cursor {
+    fn advance(mut self, data: &[u8]) -> Cursor {
+        for &b in data {
+            match b {
+                b'\n' if self.after_cr => {}
+                b'\n' | b'\r' => {
+                    self.position.line += 1;
+                    self.position.column = 1;
+                }
+                // count only the first byte of a UTF-8 sequence
+                _ if b & 0xC0 != 0x80 => self.position.column += 1,
+                _ => {}
+            }
+            self.after_cr = b == b'\r';
+        }
+        self.position.offset += self.encoding.input_len(data);
+        self
+    }
+}
+
+#[test]
+fn test_cursor_advance() {
+    let cursor = Cursor::default().advance("ab\r\nçd\re\n".as_bytes());
+    assert_eq!(cursor.position.line, 4);
+    assert_eq!(cursor.position.column, 1);
+    assert_eq!(cursor.position.offset, 10);
+
+    // `\r\n` split between two reads
+    let cursor = Cursor::default().advance(b"a\r").advance(b"\nb");
+    assert_eq!(cursor.position.line, 2);
+    assert_eq!(cursor.position.column, 2);
+
+    let cursor = Cursor::default().advance("kürk".as_bytes());
+    assert_eq!(cursor.position.column, 5);
+    assert_eq!(cursor.position.offset, 5);
 }
 
 // https://doc.rust-lang.org/nomicon/borrow-splitting.html
@@ -407,6 +1372,18 @@ mod error {
 //moving all states to read_event?
 //we can also simplify the enum here to remove duplicates,
 // then we have to move complexity to read_event method
+// parser settings and DTD state that events are converted with
+struct ConverterContext<'p> {
+    is_namespace_aware: bool,
+    line_endings: &'p mut LineEndings,
+    entities: &'p Entities,
+    attribute_defaults: &'p AttributeDefaults,
+    // elements declared with element content
+    element_content: &'p HashSet<String>,
+    // gets the #FIXED attribute mismatches
+    validator: Option<&'p mut Validator>,
+}
+
 fn event_converter<'a, 'b>(
     mut state: ParserState,
     internal_event: InternalSuccess<'b>,
@@ -414,26 +1391,34 @@ fn event_converter<'a, 'b>(
     element_list: &mut Vec<Range<usize>>,
     mut strbuffer: &'a mut String,
     namespace_strbuffer: &'a mut String,
-    namespace_list: &mut Vec<Namespace>,
+    namespace_list: &'a mut Vec<Namespace>,
 
-    is_namespace_aware: bool,
     mut element_level: usize,
     mut element_strbuffer: &mut String,
 
     attribute_list: &'a mut Vec<AttributeRange>,
-) -> SaxResult<(xml_sax::Event<'a>, ParserState, usize)> {
+    context: &mut ConverterContext,
+) -> InternalResult<(xml_sax::Event<'a>, ParserState, usize)> {
     let event = match internal_event {
         InternalSuccess::StartDocument => xml_sax::Event::StartDocument,
         InternalSuccess::EndDocument => xml_sax::Event::EndDocument,
         InternalSuccess::ContentRelaxed(cr) => match cr {
             ContentRelaxed::CharData(event1) => {
-                let start = strbuffer.len();
-                let size = event1.len();
-                strbuffer.push_str(unsafe { std::str::from_utf8_unchecked(event1) });
-                xml_sax::Event::Characters(&strbuffer[start..(start + size)])
+                let str = unsafe { std::str::from_utf8_unchecked(event1) };
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
+                if is_ignorable_whitespace(
+                    context.element_content,
+                    element_list,
+                    element_strbuffer,
+                    event1,
+                ) {
+                    xml_sax::Event::IgnorableWhitespace(&strbuffer[range])
+                } else {
+                    xml_sax::Event::Characters(&strbuffer[range])
+                }
             }
             ContentRelaxed::StartElement(event1) => {
-                if is_namespace_aware {
+                if context.is_namespace_aware {
                     // clear up namespaces
                     match namespace_list
                         .iter()
@@ -457,6 +1442,7 @@ fn event_converter<'a, 'b>(
                     strbuffer,
                     event1,
                     attribute_list,
+                    context,
                 )?;
 
                 element_level += 1;
@@ -472,9 +1458,11 @@ fn event_converter<'a, 'b>(
                 // let mut element_local_name = "";
                 // let mut element_namespace = "";
                 // let mut element_prefix = "";
+                // declarations of this element are pushed after the ones in scope
+                let first_mapping = namespace_list.len();
                 let element_ranges = parse_start_element(
                     start_element_name_range.clone(),
-                    is_namespace_aware,
+                    context.is_namespace_aware,
                     element_level,
                     strbuffer,
                     attribute_list,
@@ -492,6 +1480,7 @@ fn event_converter<'a, 'b>(
                     prefix: &strbuffer[element_ranges.prefix_range],
 
                     range_list: attribute_list,
+                    prefix_mapping_list: &namespace_list[first_mapping..],
                     strbuffer: strbuffer,
                     namespace_strbuffer: namespace_strbuffer,
                 };
@@ -499,7 +1488,7 @@ fn event_converter<'a, 'b>(
                 xml_sax::Event::StartElement(start_element)
             }
             ContentRelaxed::EmptyElemTag(event1) => {
-                if is_namespace_aware {
+                if context.is_namespace_aware {
                     // clear up namespaces
                     match namespace_list
                         .iter()
@@ -523,15 +1512,18 @@ fn event_converter<'a, 'b>(
                     strbuffer,
                     event1,
                     attribute_list,
+                    context,
                 )?;
 
                 element_level += 1; // this is important before namespace handling
 
                 // element_list_range is not important for empty element tag
 
+                // declarations of this element are pushed after the ones in scope
+                let first_mapping = namespace_list.len();
                 let element_ranges = parse_start_element(
                     start_element_name_range.clone(),
-                    is_namespace_aware,
+                    context.is_namespace_aware,
                     element_level,
                     strbuffer,
                     attribute_list,
@@ -549,6 +1541,7 @@ fn event_converter<'a, 'b>(
                     prefix: &strbuffer[element_ranges.prefix_range],
 
                     range_list: attribute_list,
+                    prefix_mapping_list: &namespace_list[first_mapping..],
                     strbuffer: strbuffer,
                     namespace_strbuffer: namespace_strbuffer,
                 };
@@ -574,22 +1567,24 @@ fn event_converter<'a, 'b>(
                         if &element_strbuffer[r.clone()] == event1.name {
                             element_strbuffer.truncate(r.start);
                         } else {
-                            return Err(error::Error::Parsing(format!(
-                                "Expected closing tag: {} ,found: {}",
-                                &element_strbuffer[r.clone()],
-                                event1.name
-                            )));
+                            return Err(error::InternalError::Parsing(
+                                error::ErrorKind::MismatchedEndTag {
+                                    expected: element_strbuffer[r.clone()].to_owned(),
+                                    found: event1.name.to_owned(),
+                                },
+                            ));
                         }
                     }
                     None => {
-                        return Err(error::Error::Parsing(format!(
-                            "No starting tag for: {}",
-                            event1.name
-                        )))
+                        return Err(error::InternalError::Parsing(
+                            error::ErrorKind::UnmatchedEndTag {
+                                name: event1.name.to_owned(),
+                            },
+                        ))
                     }
                 }
 
-                if is_namespace_aware {
+                if context.is_namespace_aware {
                     // clear up namespaces
                     match namespace_list
                         .iter()
@@ -618,11 +1613,18 @@ fn event_converter<'a, 'b>(
                 let start = strbuffer.len();
                 let size = event1.name.len();
                 strbuffer.push_str(event1.name);
+                // declarations of this element go out of scope with it
+                let first_mapping = namespace_list
+                    .iter()
+                    .rposition(|ns| ns.level < element_level)
+                    .map_or(0, |pos| pos + 1);
                 let mut end_element = xml_sax::EndElement {
                     name: &strbuffer[start..(start + size)],
                     local_name: "",
                     prefix: "",
                     namespace: "",
+                    prefix_mapping_list: &namespace_list[first_mapping..],
+                    namespace_strbuffer,
                 };
 
                 element_level -= 1;
@@ -630,7 +1632,7 @@ fn event_converter<'a, 'b>(
                     state = ParserState::DocEnd;
                 }
 
-                if is_namespace_aware {
+                if context.is_namespace_aware {
                     match QName(end_element.name.as_bytes()) {
                         Ok(qres) => {
                             let qname = qres.1;
@@ -647,19 +1649,22 @@ fn event_converter<'a, 'b>(
                                     if end_element.prefix == "" {
                                         //it is fine
                                     } else {
-                                        return Err(error::Error::Parsing(format!(
-                                            "Namespace prefix not found for element: {}",
-                                            end_element.name
-                                        )));
+                                        return Err(error::InternalError::Parsing(
+                                            error::ErrorKind::UnboundPrefix {
+                                                prefix: end_element.prefix.to_owned(),
+                                                name: end_element.name.to_owned(),
+                                            },
+                                        ));
                                     }
                                 }
                             }
                         }
                         Err(_e) => {
-                            return Err(error::Error::Parsing(format!(
-                                "Element name does not conform to QName spec: {}",
-                                end_element.name
-                            )))
+                            return Err(error::InternalError::Parsing(
+                                error::ErrorKind::InvalidQName {
+                                    name: end_element.name.to_owned(),
+                                },
+                            ))
                         }
                     }
                 }
@@ -677,39 +1682,7 @@ fn event_converter<'a, 'b>(
                 let range: Range<usize> = push_str_get_range(&mut strbuffer, event1.initial);
 
                 //we handle the case when it is a character, not a string reference
-                let raw = event1.initial;
-                let resolved_char: Option<char>;
-                if raw.starts_with("&#x") {
-                    let hex_val = &raw[3..raw.len() - 1];
-
-                    resolved_char = match u32::from_str_radix(&hex_val, 16) {
-                        Ok(a) => match char::from_u32(a) {
-                            Some(c) => Some(c),
-                            None => None,
-                        },
-                        Err(_) => None,
-                    }
-                } else if raw.starts_with("&#") {
-                    let hex_val = &raw[2..raw.len() - 1];
-
-                    resolved_char = match u32::from_str_radix(&hex_val, 10) {
-                        Ok(a) => match char::from_u32(a) {
-                            Some(c) => Some(c),
-                            None => None,
-                        },
-                        Err(_) => None,
-                    }
-                } else {
-                    resolved_char = match event1.initial {
-                        // we don't need .as_ref() or &* as it is not String -> https://github.com/rust-lang/rust/issues/28606
-                        "&amp;" => Some('&'),
-                        "&lt;" => Some('<'),
-                        "&gt;" => Some('>'),
-                        "&quot;" => Some('"'),
-                        "&apos;" => Some('\''),
-                        _ => None,
-                    }
-                }
+                let resolved_char = resolve_reference(event1.initial);
 
                 let range_resolved: Option<Range<usize>> = match resolved_char {
                     Some(ch) => {
@@ -742,83 +1715,117 @@ fn event_converter<'a, 'b>(
             ContentRelaxed::CdataStart => xml_sax::Event::StartCdataSection,
             ContentRelaxed::CommentStart => xml_sax::Event::StartComment,
             ContentRelaxed::PI(event1) => {
-                let start = strbuffer.len();
-                let size = event1.len();
-                strbuffer.push_str(unsafe { std::str::from_utf8_unchecked(event1) });
-                xml_sax::Event::ProcessingInstruction(&strbuffer[start..(start + size)])
+                let (target, data) =
+                    push_processing_instruction(strbuffer, context.line_endings, event1)?;
+                xml_sax::Event::ProcessingInstruction(xml_sax::ProcessingInstruction {
+                    target: &strbuffer[target],
+                    data: &strbuffer[data],
+                })
             }
         },
         InternalSuccess::InsideCdata(ic) => match ic {
             InsideCdata::Characters(characters) => {
-                let start = strbuffer.len();
-                let size = characters.len();
-                strbuffer.push_str(unsafe { std::str::from_utf8_unchecked(characters) });
-                xml_sax::Event::Cdata(&strbuffer[start..(start + size)])
+                let str = unsafe { std::str::from_utf8_unchecked(characters) };
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
+                xml_sax::Event::Cdata(&strbuffer[range])
             }
             InsideCdata::CdataEnd => xml_sax::Event::EndCdataSection,
         },
         InternalSuccess::InsideComment(ic) => match ic {
             InsideComment::Characters(characters) => {
-                let start = strbuffer.len();
-                let size = characters.len();
-                strbuffer.push_str(unsafe { std::str::from_utf8_unchecked(characters) });
+                let str = unsafe { std::str::from_utf8_unchecked(characters) };
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
 
-                xml_sax::Event::Comment(&strbuffer[start..(start + size)])
+                xml_sax::Event::Comment(&strbuffer[range])
             }
             InsideComment::CommentEnd => xml_sax::Event::EndComment,
         },
         InternalSuccess::Misc(misc) => match misc {
             Misc::PI(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::ProcessingInstruction(&strbuffer[range])
+                let (target, data) =
+                    push_processing_instruction(strbuffer, context.line_endings, a)?;
+                xml_sax::Event::ProcessingInstruction(xml_sax::ProcessingInstruction {
+                    target: &strbuffer[target],
+                    data: &strbuffer[data],
+                })
             }
             Misc::Whitespace(a) => {
                 let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
                 xml_sax::Event::Whitespace(&strbuffer[range])
             }
             Misc::CommentStart => xml_sax::Event::StartComment,
         },
         InternalSuccess::MiscBeforeDoctype(misc) => match misc {
             MiscBeforeDoctype::PI(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::ProcessingInstruction(&strbuffer[range])
+                let (target, data) =
+                    push_processing_instruction(strbuffer, context.line_endings, a)?;
+                xml_sax::Event::ProcessingInstruction(xml_sax::ProcessingInstruction {
+                    target: &strbuffer[target],
+                    data: &strbuffer[data],
+                })
             }
             MiscBeforeDoctype::Whitespace(a) => {
                 let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
                 xml_sax::Event::Whitespace(&strbuffer[range])
             }
             MiscBeforeDoctype::CommentStart => xml_sax::Event::StartComment,
             MiscBeforeDoctype::DocType(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::DocumentTypeDeclaration(&strbuffer[range])
+                let doctype = push_doctype(strbuffer, context.line_endings, a)?;
+                xml_sax::Event::DocumentTypeDeclaration(doctype.convert(strbuffer))
             }
         },
         InternalSuccess::MiscBeforeXmlDecl(misc) => match misc {
             MiscBeforeXmlDecl::XmlDecl(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::XmlDeclaration(&strbuffer[range])
+                let values = match XMLDecl_values(a) {
+                    Ok((_, values)) => values,
+                    Err(_) => {
+                        return Err(error::InternalError::Parsing(syntax_error(
+                            "XML declaration",
+                            a,
+                        )))
+                    }
+                };
+                // the declaration is ASCII
+                let version = unsafe { std::str::from_utf8_unchecked(values.version) };
+                let encoding = values
+                    .encoding
+                    .map(|e| unsafe { std::str::from_utf8_unchecked(e) });
+
+                // 1.x documents are processed as 1.0, XML 1.0 §2.8
+                if !version.starts_with("1.") {
+                    return Err(error::InternalError::Parsing(
+                        error::ErrorKind::UnsupportedVersion {
+                            version: version.to_owned(),
+                        },
+                    ));
+                }
+                let version = push_str_get_range(strbuffer, version);
+                let encoding = encoding.map(|e| push_str_get_range(strbuffer, e));
+                xml_sax::Event::XmlDeclaration(xml_sax::XmlDecl {
+                    version: &strbuffer[version],
+                    encoding: encoding.map(|e| &strbuffer[e]),
+                    standalone: values.standalone.map(|s| s == b"yes"),
+                })
             }
             MiscBeforeXmlDecl::PI(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::ProcessingInstruction(&strbuffer[range])
+                let (target, data) =
+                    push_processing_instruction(strbuffer, context.line_endings, a)?;
+                xml_sax::Event::ProcessingInstruction(xml_sax::ProcessingInstruction {
+                    target: &strbuffer[target],
+                    data: &strbuffer[data],
+                })
             }
             MiscBeforeXmlDecl::Whitespace(a) => {
                 let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
+                let range = context.line_endings.push_str_get_range(strbuffer, str);
                 xml_sax::Event::Whitespace(&strbuffer[range])
             }
             MiscBeforeXmlDecl::CommentStart => xml_sax::Event::StartComment,
             MiscBeforeXmlDecl::DocType(a) => {
-                let str = unsafe { std::str::from_utf8_unchecked(a) };
-                let range = push_str_get_range(&mut strbuffer, &str);
-                xml_sax::Event::DocumentTypeDeclaration(&strbuffer[range])
+                let doctype = push_doctype(strbuffer, context.line_endings, a)?;
+                xml_sax::Event::DocumentTypeDeclaration(doctype.convert(strbuffer))
             }
         },
     };
@@ -834,7 +1841,7 @@ fn read_event_splitted<'a, 'b, R: Read>(
 
     mut offset: usize,
     // document_complete: bool, //if element_level reaches 0 again , we control this via state
-) -> SaxResult<(InternalSuccess<'b>, ParserState, usize)> {
+) -> InternalResult<(InternalSuccess<'b>, ParserState, usize)> {
     let event2: InternalSuccess;
     match state {
         ParserState::Initial => {
@@ -862,7 +1869,7 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::MiscBeforeXmlDecl(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
                     //try content!
@@ -890,7 +1897,7 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::MiscBeforeDoctype(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
                     //try content!
@@ -915,12 +1922,13 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::InsideComment(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(
-                        "Expected Comment content or Comment end".to_owned(),
-                    ))
+                    return Err(error::InternalError::Parsing(syntax_error(
+                        "comment content or comment closing tag",
+                        buffer3.data(),
+                    )))
                 }
             }
         }
@@ -942,7 +1950,7 @@ fn read_event_splitted<'a, 'b, R: Read>(
                 }
 
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
                     //try content!
@@ -967,11 +1975,12 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::InsideComment(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Expecting comment content or comment closing tag "
+                    return Err(error::InternalError::Parsing(syntax_error(
+                        "comment content or comment closing tag",
+                        buffer3.data(),
                     )))
                 }
             }
@@ -1000,19 +2009,10 @@ fn read_event_splitted<'a, 'b, R: Read>(
                 }
                 // let ending = String::from_utf8_lossy(&buffer2);
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_e) => {
-                    let ending = String::from_utf8_lossy(&buffer3.data());
-                    let ending_truncated = match ending.char_indices().nth(50) {
-                        None => &ending,
-                        Some((idx, _)) => &ending[..idx],
-                    };
-
-                    return Err(error::Error::Parsing(format!(
-                        "Expected one of (CharData | element | Reference | CDSect | PI | Comment), found: {}",
-                        ending_truncated
-                    )));
+                    return Err(error::InternalError::Parsing(content_error(buffer3.data())));
                 }
             }
         }
@@ -1033,11 +2033,12 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::InsideCdata(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Expecting CDATA content or CDATA closing tag "
+                    return Err(error::InternalError::Parsing(syntax_error(
+                        "CDATA content or CDATA closing tag",
+                        buffer3.data(),
                     )))
                 }
             }
@@ -1058,11 +2059,12 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::InsideComment(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Expecting comment content or comment closing tag "
+                    return Err(error::InternalError::Parsing(syntax_error(
+                        "comment content or comment closing tag",
+                        buffer3.data(),
                     )))
                 }
             }
@@ -1089,12 +2091,14 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::Misc(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Unexpected entity/content at the end of the document."
-                    )))
+                    return Err(error::InternalError::Parsing(
+                        error::ErrorKind::ContentAfterRoot {
+                            found: snippet(buffer3.data()),
+                        },
+                    ))
                 }
             }
         }
@@ -1114,11 +2118,12 @@ fn read_event_splitted<'a, 'b, R: Read>(
                     event2 = InternalSuccess::InsideComment(parseresult.1);
                 }
                 Err(nom::Err::Incomplete(_e)) => {
-                    return Err(error::Error::UnexpectedEof);
+                    return Err(error::InternalError::UnexpectedEof);
                 }
                 Err(_err) => {
-                    return Err(error::Error::Parsing(format!(
-                        "Expecting comment content or comment closing tag "
+                    return Err(error::InternalError::Parsing(syntax_error(
+                        "comment content or comment closing tag",
+                        buffer3.data(),
                     )))
                 }
             }
@@ -1133,48 +2138,148 @@ impl<R: Read> Parser<R> {
         ParserBuilder::from_reader(reader).build()
     }
 
-    fn read_data(&mut self) -> Result<usize, std::io::Error> {
-        let newread: usize;
-        match self.bufreader.fill_buf() {
-            Ok(ok) => {
-                newread = ok.len();
+    fn read_data(&mut self) -> SaxResult<usize> {
+        let position = self.cursor.position;
+        let data = self
+            .bufreader
+            .fill_buf()
+            .map_err(|source| error::Error::Io { source, position })?;
+        let newread = data.len();
+        let detected = self.decoder.encoding().is_some();
+        let decoded = match self.decoder.decode(data, newread == 0) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                // the buffered input comes before the bad bytes
+                let cursor = self.advance(self.cursor, self.buffer3.data().len());
+                return Err(decode_error(err, cursor, &self.decoder));
             }
-            Err(err) => return Err(err),
+        };
+        let data_len = decoded.len();
+
+        //is it bigger than available space?
+        self.buffer3.shift();
+        if data_len > self.buffer3.available_space() {
+            let new_size = std::cmp::max(
+                self.buffer3.position() + data_len,
+                self.buffer3.capacity() * 2,
+            );
+
+            self.buffer3.grow(new_size);
         }
-
-        let amt: usize;
-        {
-            let data2 = self.bufreader.buffer();
-            let data_len = data2.len();
-            //is it bigger than available space?
-
-            self.buffer3.shift();
-            if data_len > self.buffer3.available_space() {
-                let new_size = std::cmp::max(
-                    self.buffer3.position() + data_len,
-                    self.buffer3.capacity() * 2,
-                );
-
-                self.buffer3.grow(new_size);
+        self.buffer3.write_all(decoded).unwrap();
+        self.entities.input_size += newread;
+        self.bufreader.consume(newread);
+
+        if let (false, Some((encoding, bom_len))) = (detected, self.decoder.encoding()) {
+            // offsets count the bytes of the input, nothing is consumed before detection
+            for cursor in [&mut self.cursor, &mut self.event_end] {
+                cursor.encoding = encoding;
+                cursor.position.offset += bom_len;
             }
+        }
+
+        // input that is kept back until the end, like a short document, comes at EOF
+        Ok(newread.max(data_len))
+    }
 
-            // self.buffer2.borrow_mut().extend_from_slice(data2);
-            // println!("buffer: {:?} , datalen: {:?}",self.buffer3.available_space(),data2.len());
-            self.buffer3.write_all(data2).unwrap();
-            // self.buffer3.spa
-            amt = data2.len();
+    // decodes the input that was read before the encoding declaration switched
+    // the encoding, it is at the start of the buffer
+    fn decode_buffered(&mut self) -> SaxResult<()> {
+        let data = self.buffer3.data().to_vec();
+        self.decoder.rewind(data.len());
+        let decoded = match self.decoder.decode(&data, false) {
+            Ok(decoded) => decoded,
+            Err(err) => return Err(decode_error(err, self.cursor, &self.decoder)),
+        };
+
+        self.buffer3.shift();
+        let needed = decoded.len();
+        if needed > self.buffer3.capacity() {
+            self.buffer3
+                .grow(std::cmp::max(needed, self.buffer3.capacity() * 2));
         }
-        self.bufreader.consume(amt);
+        self.buffer3
+            .replace_slice(decoded, 0, data.len())
+            .expect("buffer has room for the decoded input");
+
+        let (encoding, _) = self.decoder.encoding().unwrap_or_default();
+        self.cursor.encoding = encoding;
+        self.event_end.encoding = encoding;
+        Ok(())
+    }
+
+    /// Start of the most recently returned event.
+    ///
+    /// After an error it points to the input that failed to parse.
+    pub fn position(&self) -> xml_sax::Position {
+        self.cursor.position
+    }
+
+    /// The XML declaration of the document, once it is read.
+    pub fn xml_declaration(&self) -> Option<xml_sax::XmlDecl<'_>> {
+        self.xml_declaration.as_ref().map(|decl| decl.as_xml_decl())
+    }
+
+    /// The DTD of the document, once the DOCTYPE is read. The external subset is
+    /// included if the entity resolver loads it. Notations and unparsed entities are
+    /// in [Dtd::notations] and [Dtd::unparsed_entities].
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.dtd.as_ref()
+    }
+
+    /// Validity errors found so far, empty unless validation is enabled.
+    /// IDREF values are checked at the end of the document.
+    pub fn validity_errors(&self) -> &[ValidityError] {
+        self.validator
+            .as_ref()
+            .map_or(&[], |validator| validator.errors.as_slice())
+    }
 
-        Ok(newread)
+    /// Turns the parser into an iterator of owned events.
+    ///
+    /// The iterator ends after `EndDocument` or the first error.
+    pub fn into_owned_events(self) -> OwnedEvents<R> {
+        OwnedEvents {
+            parser: self,
+            finished: false,
+        }
     }
 
     // rust is not yet smart about loops, nll, structs, conditional lifetimes
 
     pub fn read_event<'a>(&'a mut self) -> SaxResult<xml_sax::Event<'a>> {
+        self.read_event_with_span().map(|(event, _span)| event)
+    }
+
+    /// Same as `read_event`, but also returns the input range of the event.
+    pub fn read_event_with_span<'a>(
+        &'a mut self,
+    ) -> SaxResult<(xml_sax::Event<'a>, xml_sax::Span)> {
+        let declaration_count = self.dtd.as_ref().map_or(0, |dtd| dtd.declarations.len());
+        if self.declaration_index < declaration_count {
+            // declarations have the span of their DOCTYPE
+            let span = xml_sax::Span {
+                start: self.cursor.position,
+                end: self.event_end.position,
+            };
+            let dtd = self.dtd.as_ref().expect("DTD with declarations");
+            let event = match &dtd.declarations[self.declaration_index] {
+                MarkupDecl::Element(decl) => xml_sax::Event::ElementDecl(decl),
+                MarkupDecl::Attlist(decl) => xml_sax::Event::AttlistDecl(decl),
+                MarkupDecl::Entity(decl) => xml_sax::Event::EntityDecl(decl),
+                MarkupDecl::Notation(decl) => xml_sax::Event::NotationDecl(decl),
+            };
+            self.declaration_index += 1;
+            return Ok((event, span));
+        }
+
         self.buffer3.consume(self.offset);
         // self.buffer2.borrow_mut().drain(0..self.offset);
         self.offset = 0;
+        self.cursor = self.event_end;
+        if std::mem::take(&mut self.redecode) {
+            self.decode_buffered()?;
+        }
         // {
         //     let vec1;
         //     {
@@ -1196,6 +2301,7 @@ impl<R: Read> Parser<R> {
         }
 
         let mut read_more_data = false;
+        let mut merged: Option<MergedCharacters> = None;
         loop {
             if read_more_data {
                 // read_data_splitted(&mut self.bufreader, &mut self.buffer2.borrow_mut())?;
@@ -1206,8 +2312,211 @@ impl<R: Read> Parser<R> {
                     read_event_splitted(self.state, &self.bufreader, &self.buffer3, self.offset);
                 match res {
                     Ok(o) => {
+                        let reference = match &o.0 {
+                            InternalSuccess::ContentRelaxed(ContentRelaxed::Reference(r))
+                                if resolve_reference(r.initial).is_none() =>
+                            {
+                                Some(&r.initial[1..r.initial.len() - 1])
+                            }
+                            _ => None,
+                        };
+                        if let Some(name) = reference {
+                            match self.entities.expands_in_content(name, &self.entity_frames) {
+                                Ok(true) => {
+                                    let name = name.to_owned();
+                                    self.state = o.1;
+                                    self.expand_entity(name, o.2);
+                                    // the replacement text is merged like a resolved reference
+                                    if let Some(m) = &mut merged {
+                                        m.after_reference = true;
+                                    }
+                                    continue;
+                                }
+                                // reported as a `Reference` event
+                                Ok(false) => {}
+                                Err(kind) => {
+                                    if let Some(m) = merged {
+                                        if !self.strbuffer.is_empty() {
+                                            return Ok(self.merged_characters(m));
+                                        }
+                                    }
+                                    return Err(error::InternalError::Parsing(kind)
+                                        .at(self.cursor.position));
+                                }
+                            }
+                        }
+
+                        if self.line_endings.normalize
+                            && bytes_read != 0
+                            && o.2 == self.buffer3.data().len()
+                            && o.0.text().is_some_and(|a| a.ends_with(b"\r"))
+                        {
+                            // the span includes the `\n` of a `\r\n` pair in the next read
+                            read_more_data = true;
+                            continue;
+                        }
+                        if self.line_endings.normalize
+                            && self.line_endings.after_cr
+                            && o.0.text() == Some(b"\n")
+                        {
+                            // `\n` of a `\r\n` pair that was split between reads
+                            let consumed = o.2;
+                            self.state = o.1;
+                            self.cursor = self.advance(self.cursor, consumed);
+                            self.event_end = self.cursor;
+                            leave_entities(
+                                &mut self.entity_frames,
+                                consumed,
+                                true,
+                                self.state,
+                                self.element_level,
+                            )
+                            .map_err(|kind| {
+                                error::InternalError::Parsing(kind).at(self.cursor.position)
+                            })?;
+                            self.buffer3.consume(consumed);
+                            self.line_endings.after_cr = false;
+                            continue;
+                        }
+                        if self.merge_references || self.coalescing.is_some() {
+                            let cdata =
+                                matches!(self.coalescing, Some(Coalescing { cdata: true, .. }));
+                            let (mut text, resolved, cdata_marker) = match &o.0 {
+                                InternalSuccess::ContentRelaxed(ContentRelaxed::CharData(a)) => {
+                                    (Some(*a), None, false)
+                                }
+                                InternalSuccess::ContentRelaxed(ContentRelaxed::Reference(r)) => {
+                                    (None, resolve_reference(r.initial), false)
+                                }
+                                InternalSuccess::ContentRelaxed(ContentRelaxed::CdataStart)
+                                | InternalSuccess::InsideCdata(InsideCdata::CdataEnd) => {
+                                    (None, None, cdata)
+                                }
+                                InternalSuccess::InsideCdata(InsideCdata::Characters(a))
+                                    if cdata =>
+                                {
+                                    (Some(*a), None, false)
+                                }
+                                _ => (None, None, false),
+                            };
+                            // without coalescing, text is merged only around references
+                            let (continues, limit) = match &self.coalescing {
+                                Some(c) => (true, c.max_size),
+                                None => (
+                                    resolved.is_some()
+                                        || merged.as_ref().is_none_or(|m| m.after_reference),
+                                    self.bufreader.capacity(),
+                                ),
+                            };
+                            let room = limit.saturating_sub(self.strbuffer.len());
+                            let mut consumed = o.2;
+                            if let Some(a) = text {
+                                if a.len() > room && self.coalescing.is_some() {
+                                    // flush at the limit, the rest comes in the next event
+                                    let mut end = room;
+                                    while end > 0 && (a[end] & 0xC0) == 0x80 {
+                                        end -= 1;
+                                    }
+                                    consumed = self.buffer3.data().offset(a) + end;
+                                    text = if end == 0 { None } else { Some(&a[..end]) };
+                                } else if room == 0 {
+                                    text = None;
+                                }
+                            }
+                            let is_piece = text.is_some()
+                                || resolved.is_some_and(|c| c.len_utf8() <= room)
+                                || cdata_marker;
+
+                            if continues && is_piece {
+                                if let Some(a) = text {
+                                    let str = unsafe { std::str::from_utf8_unchecked(a) };
+                                    self.line_endings
+                                        .push_str_get_range(&mut self.strbuffer, str);
+                                } else {
+                                    self.line_endings.after_cr = false;
+                                }
+                                if let Some(c) = resolved {
+                                    self.strbuffer.push(c);
+                                }
+                                merged = Some(MergedCharacters {
+                                    start: match &merged {
+                                        Some(m) => m.start,
+                                        None => self.cursor.position,
+                                    },
+                                    after_reference: resolved.is_some(),
+                                    cdata: cdata_marker || merged.as_ref().is_some_and(|m| m.cdata),
+                                    references: resolved.is_some()
+                                        || merged.is_some_and(|m| m.references),
+                                });
+
+                                self.state = o.1;
+                                self.cursor = self.advance(self.cursor, consumed);
+                                self.event_end = self.cursor;
+                                leave_entities(
+                                    &mut self.entity_frames,
+                                    consumed,
+                                    text.is_some(),
+                                    self.state,
+                                    self.element_level,
+                                )
+                                .map_err(|kind| {
+                                    error::InternalError::Parsing(kind).at(self.cursor.position)
+                                })?;
+                                self.buffer3.consume(consumed);
+                                continue;
+                            }
+                            if let Some(m) = merged.take() {
+                                // an empty CDATA section alone doesn't make an event
+                                if self.skip_ignorable_whitespace
+                                    && m.may_be_ignorable()
+                                    && self.is_ignorable_whitespace(self.strbuffer.as_bytes())
+                                {
+                                    self.strbuffer.clear();
+                                } else if !self.strbuffer.is_empty() {
+                                    return Ok(self.merged_characters(m));
+                                }
+                            }
+                        }
+
+                        let ignorable = match &o.0 {
+                            InternalSuccess::ContentRelaxed(ContentRelaxed::CharData(a)) => {
+                                self.is_ignorable_whitespace(a)
+                            }
+                            _ => false,
+                        };
+                        if ignorable && self.skip_ignorable_whitespace {
+                            let consumed = o.2;
+                            self.line_endings.after_cr =
+                                o.0.text().is_some_and(|a| a.ends_with(b"\r"));
+                            self.state = o.1;
+                            self.cursor = self.advance(self.cursor, consumed);
+                            self.event_end = self.cursor;
+                            leave_entities(
+                                &mut self.entity_frames,
+                                consumed,
+                                true,
+                                self.state,
+                                self.element_level,
+                            )
+                            .map_err(|kind| {
+                                error::InternalError::Parsing(kind).at(self.cursor.position)
+                            })?;
+                            self.buffer3.consume(consumed);
+                            continue;
+                        }
+
+                        let is_text = o.0.text().is_some();
+                        if !is_text {
+                            self.line_endings.after_cr = false;
+                        }
+
                         self.state = o.1;
                         self.offset = o.2;
+                        self.event_end = self.advance(self.cursor, self.offset);
+                        let span = xml_sax::Span {
+                            start: self.cursor.position,
+                            end: self.event_end.position,
+                        };
 
                         // event1 = o.0;
 
@@ -1219,38 +2528,205 @@ impl<R: Read> Parser<R> {
                             &mut self.strbuffer,
                             &mut self.namespace_strbuffer,
                             &mut self.namespace_list,
-                            self.is_namespace_aware,
                             self.element_level,
                             &mut self.element_strbuffer,
                             &mut self.attribute_list,
+                            &mut ConverterContext {
+                                is_namespace_aware: self.is_namespace_aware,
+                                line_endings: &mut self.line_endings,
+                                entities: &self.entities,
+                                attribute_defaults: &self.attribute_defaults,
+                                element_content: &self.element_content,
+                                validator: self.validator.as_mut(),
+                            },
                         );
                         match event {
                             Ok(tpl) => {
                                 self.state = tpl.1;
                                 self.element_level = tpl.2;
+                                leave_entities(
+                                    &mut self.entity_frames,
+                                    self.offset,
+                                    is_text,
+                                    self.state,
+                                    self.element_level,
+                                )
+                                .map_err(|kind| {
+                                    error::InternalError::Parsing(kind).at(span.start)
+                                })?;
+                                if let xml_sax::Event::XmlDeclaration(decl) = &tpl.0 {
+                                    if let Some(encoding) = decl.encoding {
+                                        self.redecode =
+                                            self.decoder.declared(encoding).map_err(|kind| {
+                                                error::InternalError::Parsing(kind).at(span.start)
+                                            })?;
+                                    }
+                                    self.xml_declaration = Some(decl.into());
+                                }
+                                if let xml_sax::Event::DocumentTypeDeclaration(doctype) = &tpl.0 {
+                                    let standalone = self
+                                        .xml_declaration
+                                        .as_ref()
+                                        .is_some_and(|decl| decl.standalone == Some(true));
+                                    let dtd = read_dtd(
+                                        doctype,
+                                        standalone,
+                                        &mut self.entities,
+                                        &mut self.attribute_defaults,
+                                    )
+                                    .map_err(|kind| {
+                                        error::InternalError::Parsing(kind).at(span.start)
+                                    })?;
+                                    self.declaration_index = if self.report_declarations {
+                                        0
+                                    } else {
+                                        dtd.declarations.len()
+                                    };
+                                    if let Some(validator) = &mut self.validator {
+                                        validator.declare(&dtd);
+                                    }
+                                    self.element_content = element_content(&dtd);
+                                    self.dtd = Some(dtd);
+                                }
+                                let event = tpl.0;
+                                if let Some(validator) = &mut self.validator {
+                                    validator.event(&event, span.start);
+                                }
 
-                                return Ok(tpl.0);
+                                return Ok((event, span));
                             }
-                            Err(err) => return Err(err),
+                            Err(err) => return Err(err.at(span.start)),
                         };
                     }
-                    Err(error::Error::UnexpectedEof) => {
+                    Err(error::InternalError::UnexpectedEof) => {
                         //check eof increase internal buffer.
                         //try reading again
                         // read_data_splitted_refcell(&mut self.bufreader, &self.buffer2)?;
                         if bytes_read == 0 {
-                            return Err(error::Error::UnexpectedEof);
+                            if let Some(m) = merged {
+                                if !self.strbuffer.is_empty() {
+                                    return Ok(self.merged_characters(m));
+                                }
+                            }
+                            return Err(self.eof_error());
                         } else {
                             read_more_data = true;
                         }
                     }
                     Err(err) => {
-                        return Err(err);
+                        // the error is reported again on the next call
+                        if let Some(m) = merged {
+                            if !self.strbuffer.is_empty() {
+                                return Ok(self.merged_characters(m));
+                            }
+                        }
+                        return Err(err.at(self.cursor.position));
                     }
                 }
             }
         }
     }
+
+    // replacement text of entities is not input, only the rest moves the cursor
+    fn advance(&self, cursor: Cursor, consumed: usize) -> Cursor {
+        let replaced = self
+            .entity_frames
+            .first()
+            .map_or(0, |frame| frame.end.min(consumed));
+        cursor.advance(&self.buffer3.data()[replaced..consumed])
+    }
+
+    // replaces the reference at the start of the buffer with the replacement text
+    fn expand_entity(&mut self, name: String, reference_len: usize) {
+        let Some(text) = self.entities.replacement(&name) else {
+            return;
+        };
+        self.cursor = self.advance(self.cursor, reference_len);
+        self.event_end = self.cursor;
+
+        let needed = self.buffer3.available_data() - reference_len + text.len();
+        self.buffer3.shift();
+        if needed > self.buffer3.capacity() {
+            self.buffer3
+                .grow(std::cmp::max(needed, self.buffer3.capacity() * 2));
+        }
+        self.buffer3
+            .replace_slice(text.as_bytes(), 0, reference_len)
+            .expect("buffer has room for the replacement text");
+
+        for frame in self.entity_frames.iter_mut() {
+            frame.end = frame.end - reference_len + text.len();
+        }
+        if !text.is_empty() {
+            self.entity_frames.push(EntityFrame {
+                name,
+                end: text.len(),
+                element_level: self.element_level,
+            });
+        }
+    }
+
+    fn merged_characters(
+        &mut self,
+        merged: MergedCharacters,
+    ) -> (xml_sax::Event<'_>, xml_sax::Span) {
+        let span = xml_sax::Span {
+            start: merged.start,
+            end: self.cursor.position,
+        };
+        let event = if merged.may_be_ignorable()
+            && self.is_ignorable_whitespace(self.strbuffer.as_bytes())
+        {
+            xml_sax::Event::IgnorableWhitespace(&self.strbuffer)
+        } else {
+            xml_sax::Event::Characters(&self.strbuffer)
+        };
+        if let Some(validator) = &mut self.validator {
+            if merged.cdata {
+                validator.event(&xml_sax::Event::StartCdataSection, span.start);
+            }
+            match event {
+                xml_sax::Event::Characters(text) => {
+                    validator.text(text, merged.references, span.start);
+                }
+                _ => validator.event(&event, span.start),
+            }
+        }
+        (event, span)
+    }
+
+    fn is_ignorable_whitespace(&self, text: &[u8]) -> bool {
+        is_ignorable_whitespace(
+            &self.element_content,
+            &self.element_list,
+            &self.element_strbuffer,
+            text,
+        )
+    }
+
+    fn eof_error(&self) -> error::Error {
+        let position = self.cursor.position;
+        if !self.buffer3.data().is_empty() {
+            return error::Error::UnexpectedEof { position };
+        }
+        let kind = match self.state {
+            ParserState::Content if !self.element_list.is_empty() => {
+                error::ErrorKind::UnclosedElements {
+                    names: self
+                        .element_list
+                        .iter()
+                        .map(|range| self.element_strbuffer[range.clone()].to_owned())
+                        .collect(),
+                }
+            }
+            ParserState::DocStartBeforeXmlDecl
+            | ParserState::DocStartBeforeDocType
+            | ParserState::DocStart
+            | ParserState::Content => error::ErrorKind::NoRootElement,
+            _ => return error::Error::UnexpectedEof { position },
+        };
+        error::Error::Parsing { kind, position }
+    }
 }
 
 #[test]
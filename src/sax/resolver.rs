@@ -0,0 +1,179 @@
+// Resolvers for the external DTD subset and external parsed entities
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+/// Opens external entities for the parser.
+///
+/// `base_uri` is the URI of the document or entity that declares the external entity,
+/// relative system identifiers can be joined to it with [resolve_uri].
+pub trait EntityResolver {
+    /// `Ok(None)` leaves the entity unresolved: the external subset is skipped and
+    /// references to the entity are reported as `Reference` events.
+    fn resolve(
+        &self,
+        public_id: Option<&str>,
+        system_id: &str,
+        base_uri: Option<&str>,
+    ) -> io::Result<Option<Box<dyn Read>>>;
+}
+
+/// Doesn't resolve any entity, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DenyResolver;
+
+impl EntityResolver for DenyResolver {
+    fn resolve(
+        &self,
+        _public_id: Option<&str>,
+        _system_id: &str,
+        _base_uri: Option<&str>,
+    ) -> io::Result<Option<Box<dyn Read>>> {
+        Ok(None)
+    }
+}
+
+/// Reads files under a root directory. URIs are paths relative to the root, `file:`
+/// URIs and absolute paths are relative to the root as well. Other schemes and paths
+/// that leave the root are `PermissionDenied` errors.
+#[derive(Clone, Debug)]
+pub struct FileResolver {
+    root: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(root: impl Into<PathBuf>) -> FileResolver {
+        FileResolver { root: root.into() }
+    }
+
+    fn path(&self, uri: &str) -> io::Result<PathBuf> {
+        let denied = || {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("outside of the resolver root: {}", uri),
+            )
+        };
+        let path = match uri
+            .strip_prefix("file://")
+            .or_else(|| uri.strip_prefix("file:"))
+        {
+            Some(path) => path,
+            None if scheme(uri).is_some() => return Err(denied()),
+            None => uri,
+        };
+
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(denied());
+                    }
+                }
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+
+        // symbolic links can't point out of the root either
+        let root = self.root.canonicalize()?;
+        let path = root.join(relative).canonicalize()?;
+        if !path.starts_with(&root) {
+            return Err(denied());
+        }
+        Ok(path)
+    }
+}
+
+impl EntityResolver for FileResolver {
+    fn resolve(
+        &self,
+        _public_id: Option<&str>,
+        system_id: &str,
+        base_uri: Option<&str>,
+    ) -> io::Result<Option<Box<dyn Read>>> {
+        let path = self.path(&resolve_uri(system_id, base_uri))?;
+        Ok(Some(Box::new(File::open(path)?)))
+    }
+}
+
+/// Serves entities from memory. Public identifiers are looked up first, then the
+/// system identifier joined to the base URI, then the system identifier as written.
+/// Unknown entities are `NotFound` errors.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryResolver {
+    entities: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> MemoryResolver {
+        MemoryResolver::default()
+    }
+
+    /// Adds an entity by its public identifier or URI.
+    pub fn insert(&mut self, id: impl Into<String>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.entities.insert(id.into(), data.into());
+        self
+    }
+}
+
+impl EntityResolver for MemoryResolver {
+    fn resolve(
+        &self,
+        public_id: Option<&str>,
+        system_id: &str,
+        base_uri: Option<&str>,
+    ) -> io::Result<Option<Box<dyn Read>>> {
+        let data = public_id
+            .and_then(|id| self.entities.get(id))
+            .or_else(|| self.entities.get(&resolve_uri(system_id, base_uri)))
+            .or_else(|| self.entities.get(system_id))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("unknown entity: {}", system_id),
+                )
+            })?;
+        Ok(Some(Box::new(io::Cursor::new(data.clone()))))
+    }
+}
+
+/// Joins a relative system identifier to the directory of the base URI.
+/// Identifiers with a scheme or an absolute path are returned as they are.
+pub fn resolve_uri(system_id: &str, base_uri: Option<&str>) -> String {
+    match base_uri {
+        Some(base) if scheme(system_id).is_none() && !system_id.starts_with('/') => {
+            let directory = base.rfind('/').map_or("", |end| &base[..=end]);
+            format!("{}{}", directory, system_id)
+        }
+        _ => system_id.to_owned(),
+    }
+}
+
+// `http` of `http://example.com`, RFC 3986 §3.1
+pub(crate) fn scheme(uri: &str) -> Option<&str> {
+    let end = uri.find(':')?;
+    let scheme = &uri[..end];
+    let mut chars = scheme.chars();
+    let valid = chars.next()?.is_ascii_alphabetic()
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    // a single letter is a Windows drive
+    (valid && scheme.len() > 1).then_some(scheme)
+}
+
+#[test]
+fn test_resolve_uri() {
+    assert_eq!(resolve_uri("a.ent", None), "a.ent");
+    assert_eq!(resolve_uri("a.ent", Some("doc.xml")), "a.ent");
+    assert_eq!(resolve_uri("a.ent", Some("dtd/doc.dtd")), "dtd/a.ent");
+    assert_eq!(
+        resolve_uri("../a.ent", Some("http://example.com/dtd/doc.dtd")),
+        "http://example.com/dtd/../a.ent"
+    );
+    assert_eq!(resolve_uri("/a.ent", Some("dtd/doc.dtd")), "/a.ent");
+    assert_eq!(resolve_uri("urn:x:a", Some("dtd/doc.dtd")), "urn:x:a");
+}
@@ -0,0 +1,608 @@
+// Validity checks against the DTD, XML 1.0 §3
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::sax::{
+    declaration::{
+        AttDef, AttType, ContentParticle, ContentSpec, DefaultDecl, Dtd, EntityDef, MarkupDecl,
+        ParticleKind, Repetition,
+    },
+    internal::{is_namechar_t, is_namestart_char_t},
+    Event, Position, StartElement,
+};
+
+/// A validity constraint the document breaks. Parsing goes on after validity errors.
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+#[error("Validity Err: {kind} at {position}")]
+pub struct ValidityError {
+    pub kind: ValidityErrorKind,
+    /// Start of the event that breaks the constraint
+    pub position: Position,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+#[non_exhaustive]
+pub enum ValidityErrorKind {
+    #[error("Document has no DOCTYPE declaration")]
+    MissingDoctype,
+
+    #[error("Root element doesn't match the DOCTYPE name: {expected} , found: {found}")]
+    RootElement { expected: String, found: String },
+
+    #[error("Element is not declared: {name}")]
+    UndeclaredElement { name: String },
+
+    #[error("Content of element {element} doesn't allow: {found}")]
+    UnexpectedContent { element: String, found: String },
+
+    #[error("Content of element {element} is incomplete")]
+    IncompleteContent { element: String },
+
+    #[error("Attribute is not declared in element: {element} , attribute: {name}")]
+    UndeclaredAttribute { element: String, name: String },
+
+    #[error("Invalid attribute value in element: {element} , attribute: {name} , value: {value}")]
+    InvalidAttributeValue {
+        element: String,
+        name: String,
+        value: String,
+    },
+
+    #[error("#FIXED attribute value differs from the declared one in element: {element} , attribute: {name} , expected: {expected:?} , found: {found:?}")]
+    FixedAttributeMismatch {
+        element: String,
+        name: String,
+        expected: String,
+        found: String,
+    },
+
+    #[error("Required attribute is missing in element: {element} , attribute: {name}")]
+    MissingAttribute { element: String, name: String },
+
+    #[error("ID value is not unique: {value}")]
+    DuplicateId { value: String },
+
+    #[error("IDREF doesn't match any ID: {value}")]
+    UndefinedIdRef { value: String },
+}
+
+// compiled `contentspec` of an element declaration
+enum ContentModel {
+    Empty,
+    Any,
+    Mixed(HashSet<String>),
+    Children(Automaton),
+}
+
+// Thompson NFA of a content particle, the states are indices
+struct Automaton {
+    states: Vec<State>,
+    start: usize,
+    accept: usize,
+}
+
+#[derive(Default)]
+struct State {
+    // element name and the state it leads to
+    transition: Option<(String, usize)>,
+    epsilon: Vec<usize>,
+}
+
+impl Automaton {
+    fn new(particle: &ContentParticle) -> Automaton {
+        let mut automaton = Automaton {
+            states: Vec::new(),
+            start: 0,
+            accept: 0,
+        };
+        let (start, accept) = automaton.compile(particle);
+        automaton.start = start;
+        automaton.accept = accept;
+        automaton
+    }
+
+    fn state(&mut self) -> usize {
+        self.states.push(State::default());
+        self.states.len() - 1
+    }
+
+    // returns the start and the accepting state of the particle
+    fn compile(&mut self, particle: &ContentParticle) -> (usize, usize) {
+        let (first, last) = match &particle.kind {
+            ParticleKind::Name(name) => {
+                let first = self.state();
+                let last = self.state();
+                self.states[first].transition = Some((name.clone(), last));
+                (first, last)
+            }
+            ParticleKind::Seq(particles) => {
+                let first = self.state();
+                let mut last = first;
+                for particle in particles {
+                    let (start, accept) = self.compile(particle);
+                    self.states[last].epsilon.push(start);
+                    last = accept;
+                }
+                (first, last)
+            }
+            ParticleKind::Choice(particles) => {
+                let first = self.state();
+                let last = self.state();
+                for particle in particles {
+                    let (start, accept) = self.compile(particle);
+                    self.states[first].epsilon.push(start);
+                    self.states[accept].epsilon.push(last);
+                }
+                (first, last)
+            }
+        };
+        if particle.repetition == Repetition::Once {
+            return (first, last);
+        }
+
+        let start = self.state();
+        let accept = self.state();
+        self.states[start].epsilon.push(first);
+        self.states[last].epsilon.push(accept);
+        if matches!(
+            particle.repetition,
+            Repetition::Optional | Repetition::ZeroOrMore
+        ) {
+            self.states[start].epsilon.push(accept);
+        }
+        if matches!(
+            particle.repetition,
+            Repetition::ZeroOrMore | Repetition::OneOrMore
+        ) {
+            self.states[last].epsilon.push(first);
+        }
+        (start, accept)
+    }
+
+    fn initial(&self) -> Vec<usize> {
+        self.closure(vec![self.start])
+    }
+
+    // states after a child element, empty if the element is not allowed
+    fn step(&self, states: &[usize], name: &str) -> Vec<usize> {
+        let next = states
+            .iter()
+            .filter_map(|&state| match &self.states[state].transition {
+                Some((n, next)) if n == name => Some(*next),
+                _ => None,
+            })
+            .collect();
+        self.closure(next)
+    }
+
+    fn accepts(&self, states: &[usize]) -> bool {
+        states.contains(&self.accept)
+    }
+
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut index = 0;
+        while index < states.len() {
+            for &next in &self.states[states[index]].epsilon {
+                if !states.contains(&next) {
+                    states.push(next);
+                }
+            }
+            index += 1;
+        }
+        states
+    }
+}
+
+struct OpenElement {
+    name: String,
+    // automaton states for element content
+    states: Vec<usize>,
+    // later content errors of an invalid element are not reported
+    failed: bool,
+}
+
+/// Checks the event stream against the DTD of the document.
+pub(crate) struct Validator {
+    // None until the DOCTYPE is read
+    root: Option<String>,
+    elements: HashMap<String, ContentModel>,
+    attributes: HashMap<String, Vec<AttDef>>,
+    unparsed_entities: HashSet<String>,
+
+    open: Vec<OpenElement>,
+    ids: HashSet<String>,
+    // IDREF values are checked at the end of the document
+    idrefs: Vec<(String, Position)>,
+    // without a DOCTYPE only the missing DOCTYPE is reported
+    disabled: bool,
+    // found while the next event is converted, reported with its position
+    pending: Vec<ValidityErrorKind>,
+    pub(crate) errors: Vec<ValidityError>,
+}
+
+impl Validator {
+    pub(crate) fn new() -> Validator {
+        Validator {
+            root: None,
+            elements: HashMap::new(),
+            attributes: HashMap::new(),
+            unparsed_entities: HashSet::new(),
+            open: Vec::new(),
+            ids: HashSet::new(),
+            idrefs: Vec::new(),
+            disabled: false,
+            pending: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn declare(&mut self, dtd: &Dtd) {
+        self.root = Some(dtd.name.clone());
+        let mut entities = HashSet::new();
+        for decl in &dtd.declarations {
+            match decl {
+                MarkupDecl::Element(el) if !self.elements.contains_key(&el.name) => {
+                    let model = match &el.content_spec {
+                        ContentSpec::Empty => ContentModel::Empty,
+                        ContentSpec::Any => ContentModel::Any,
+                        ContentSpec::Mixed(names) => {
+                            ContentModel::Mixed(names.iter().cloned().collect())
+                        }
+                        ContentSpec::Children(particle) => {
+                            ContentModel::Children(Automaton::new(particle))
+                        }
+                    };
+                    self.elements.insert(el.name.clone(), model);
+                }
+                MarkupDecl::Attlist(list) if !self.attributes.contains_key(&list.element) => {
+                    let definitions = dtd.attributes(&list.element).into_iter().cloned();
+                    self.attributes
+                        .insert(list.element.clone(), definitions.collect());
+                }
+                // the first declaration of an entity is binding
+                MarkupDecl::Entity(entity)
+                    if !entity.parameter && entities.insert(&entity.name) =>
+                {
+                    if let EntityDef::External {
+                        notation: Some(_), ..
+                    } = &entity.definition
+                    {
+                        self.unparsed_entities.insert(entity.name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn pending_error(&mut self, kind: ValidityErrorKind) {
+        self.pending.push(kind);
+    }
+
+    pub(crate) fn event(&mut self, event: &Event, position: Position) {
+        if let Event::Characters(text) = event {
+            self.text(text, false, position);
+            return;
+        }
+        for kind in std::mem::take(&mut self.pending) {
+            self.error(kind, position);
+        }
+        if self.disabled {
+            return;
+        }
+        match event {
+            Event::StartElement(el) => {
+                self.start_element(el, position);
+                if el.is_empty {
+                    self.end_element(position);
+                }
+            }
+            Event::EndElement(_) => self.end_element(position),
+            Event::StartCdataSection => {
+                self.content(
+                    position,
+                    |model| matches!(model, ContentModel::Any | ContentModel::Mixed(_)),
+                    || "CDATA section".to_owned(),
+                );
+            }
+            Event::Reference(reference) => {
+                // resolved references are character data, other ones can be markup
+                let resolved = reference.resolved.is_some();
+                self.content(
+                    position,
+                    |model| match model {
+                        ContentModel::Empty => false,
+                        ContentModel::Children(_) => !resolved,
+                        _ => true,
+                    },
+                    || format!("reference {}", reference.raw),
+                );
+            }
+            Event::StartComment => {
+                self.content(
+                    position,
+                    |model| !matches!(model, ContentModel::Empty),
+                    || "comment".to_owned(),
+                );
+            }
+            Event::ProcessingInstruction(pi) => {
+                self.content(
+                    position,
+                    |model| !matches!(model, ContentModel::Empty),
+                    || format!("processing instruction {}", pi.target),
+                );
+            }
+            Event::EndDocument => self.end_document(),
+            _ => {}
+        }
+    }
+
+    // `references` is set if character references are merged into the text,
+    // element content only allows whitespace written as is
+    pub(crate) fn text(&mut self, text: &str, references: bool, position: Position) {
+        for kind in std::mem::take(&mut self.pending) {
+            self.error(kind, position);
+        }
+        if self.disabled {
+            return;
+        }
+        let whitespace = !references && text.chars().all(|c| matches!(c, ' ' | '\t' | '\r' | '\n'));
+        self.content(
+            position,
+            |model| match model {
+                ContentModel::Empty => false,
+                ContentModel::Children(_) => whitespace,
+                _ => true,
+            },
+            || "text".to_owned(),
+        );
+    }
+
+    fn start_element(&mut self, el: &StartElement, position: Position) {
+        match (&self.root, self.open.is_empty()) {
+            (None, _) => {
+                self.disabled = true;
+                self.error(ValidityErrorKind::MissingDoctype, position);
+                return;
+            }
+            (Some(root), true) if root != el.name => {
+                let kind = ValidityErrorKind::RootElement {
+                    expected: root.clone(),
+                    found: el.name.to_owned(),
+                };
+                self.error(kind, position);
+            }
+            _ => {}
+        }
+
+        if let Some(parent) = self.open.last_mut() {
+            if !parent.failed {
+                let allowed = match self.elements.get(&parent.name) {
+                    Some(ContentModel::Empty) => false,
+                    Some(ContentModel::Mixed(names)) => names.contains(el.name),
+                    Some(ContentModel::Children(automaton)) => {
+                        parent.states = automaton.step(&parent.states, el.name);
+                        !parent.states.is_empty()
+                    }
+                    Some(ContentModel::Any) | None => true,
+                };
+                if !allowed {
+                    parent.failed = true;
+                    let kind = ValidityErrorKind::UnexpectedContent {
+                        element: parent.name.clone(),
+                        found: format!("element {}", el.name),
+                    };
+                    self.error(kind, position);
+                }
+            }
+        }
+
+        let states = match self.elements.get(el.name) {
+            Some(ContentModel::Children(automaton)) => automaton.initial(),
+            Some(_) => Vec::new(),
+            None => {
+                let kind = ValidityErrorKind::UndeclaredElement {
+                    name: el.name.to_owned(),
+                };
+                self.error(kind, position);
+                Vec::new()
+            }
+        };
+        self.open.push(OpenElement {
+            name: el.name.to_owned(),
+            states,
+            failed: false,
+        });
+
+        self.check_attributes(el, position);
+    }
+
+    fn check_attributes(&mut self, el: &StartElement, position: Position) {
+        let mut errors = Vec::new();
+        let definitions = self
+            .attributes
+            .get(el.name)
+            .map_or(&[][..], |definitions| definitions.as_slice());
+        for attribute in el.attributes() {
+            let Some(def) = definitions.iter().find(|def| def.name == attribute.name) else {
+                // attributes of undeclared elements are not reported one by one
+                if self.elements.contains_key(el.name) {
+                    errors.push(ValidityErrorKind::UndeclaredAttribute {
+                        element: el.name.to_owned(),
+                        name: attribute.name.to_owned(),
+                    });
+                }
+                continue;
+            };
+            let value = attribute.value;
+            let valid = match &def.att_type {
+                AttType::Cdata => true,
+                AttType::Id => {
+                    if is_name(value) && !self.ids.insert(value.to_owned()) {
+                        errors.push(ValidityErrorKind::DuplicateId {
+                            value: value.to_owned(),
+                        });
+                    }
+                    is_name(value)
+                }
+                AttType::IdRef => {
+                    self.idrefs.push((value.to_owned(), position));
+                    is_name(value)
+                }
+                AttType::IdRefs => {
+                    let names = value.split(' ');
+                    self.idrefs
+                        .extend(names.clone().map(|name| (name.to_owned(), position)));
+                    names.clone().all(is_name)
+                }
+                AttType::Entity => self.unparsed_entities.contains(value),
+                AttType::Entities => value
+                    .split(' ')
+                    .all(|name| self.unparsed_entities.contains(name)),
+                AttType::NmToken => is_nmtoken(value),
+                AttType::NmTokens => value.split(' ').all(is_nmtoken),
+                AttType::Notation(names) | AttType::Enumeration(names) => {
+                    names.iter().any(|name| name == value)
+                }
+            };
+            if !valid {
+                errors.push(ValidityErrorKind::InvalidAttributeValue {
+                    element: el.name.to_owned(),
+                    name: attribute.name.to_owned(),
+                    value: value.to_owned(),
+                });
+            }
+        }
+
+        for def in definitions {
+            if def.default == DefaultDecl::Required
+                && !el.attributes().any(|attribute| attribute.name == def.name)
+            {
+                errors.push(ValidityErrorKind::MissingAttribute {
+                    element: el.name.to_owned(),
+                    name: def.name.clone(),
+                });
+            }
+        }
+
+        for kind in errors {
+            self.error(kind, position);
+        }
+    }
+
+    fn end_element(&mut self, position: Position) {
+        let Some(el) = self.open.pop() else {
+            return;
+        };
+        if el.failed {
+            return;
+        }
+        if let Some(ContentModel::Children(automaton)) = self.elements.get(&el.name) {
+            if !automaton.accepts(&el.states) {
+                self.error(
+                    ValidityErrorKind::IncompleteContent { element: el.name },
+                    position,
+                );
+            }
+        }
+    }
+
+    // content other than elements, `found` describes it for the error
+    fn content(
+        &mut self,
+        position: Position,
+        allowed: impl Fn(&ContentModel) -> bool,
+        found: impl Fn() -> String,
+    ) {
+        let Some(el) = self.open.last_mut() else {
+            return;
+        };
+        if el.failed {
+            return;
+        }
+        let Some(model) = self.elements.get(&el.name) else {
+            return;
+        };
+        if !allowed(model) {
+            el.failed = true;
+            let kind = ValidityErrorKind::UnexpectedContent {
+                element: el.name.clone(),
+                found: found(),
+            };
+            self.error(kind, position);
+        }
+    }
+
+    fn end_document(&mut self) {
+        let idrefs = std::mem::take(&mut self.idrefs);
+        for (value, position) in idrefs {
+            if !self.ids.contains(&value) {
+                self.error(ValidityErrorKind::UndefinedIdRef { value }, position);
+            }
+        }
+    }
+
+    fn error(&mut self, kind: ValidityErrorKind, position: Position) {
+        self.errors.push(ValidityError { kind, position });
+    }
+}
+
+fn is_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars.next().is_some_and(is_namestart_char_t) && chars.all(is_namechar_t)
+}
+
+fn is_nmtoken(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(is_namechar_t)
+}
+
+#[test]
+fn test_automaton() {
+    let name = |name: &str, repetition| ContentParticle {
+        kind: ParticleKind::Name(name.to_owned()),
+        repetition,
+    };
+    // (head, (p | list)+, foot?)
+    let particle = ContentParticle {
+        kind: ParticleKind::Seq(vec![
+            name("head", Repetition::Once),
+            ContentParticle {
+                kind: ParticleKind::Choice(vec![
+                    name("p", Repetition::Once),
+                    name("list", Repetition::Once),
+                ]),
+                repetition: Repetition::OneOrMore,
+            },
+            name("foot", Repetition::Optional),
+        ]),
+        repetition: Repetition::Once,
+    };
+    let automaton = Automaton::new(&particle);
+    let matches = |children: &[&str]| {
+        let mut states = automaton.initial();
+        for child in children {
+            states = automaton.step(&states, child);
+        }
+        automaton.accepts(&states)
+    };
+
+    assert!(matches(&["head", "p"]));
+    assert!(matches(&["head", "list", "p", "p", "foot"]));
+    assert!(!matches(&[]));
+    assert!(!matches(&["head"]));
+    assert!(!matches(&["head", "foot"]));
+    assert!(!matches(&["head", "p", "foot", "p"]));
+    assert!(!matches(&["p", "head"]));
+}
+
+#[test]
+fn test_is_name() {
+    assert!(is_name("a-1"));
+    assert!(is_name("_x:y"));
+    assert!(!is_name("1a"));
+    assert!(!is_name(""));
+    assert!(is_nmtoken("1a"));
+    assert!(!is_nmtoken("a b"));
+}
@@ -0,0 +1,72 @@
+// Fixtures shared by the integration tests, each test uses a part of them
+#![allow(dead_code)]
+
+use std::io::Read;
+
+use xml_oxide::sax::{
+    parser::{
+        error::{Error, ErrorKind},
+        Parser,
+    },
+    Event,
+};
+
+// returns one byte per read call so markup, text and code units are split between reads
+pub struct OneByteReader<'a>(pub &'a [u8]);
+
+impl<'a> Read for OneByteReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+// tags with their attributes, text and references as strings
+pub fn content_events<R: Read>(mut p: Parser<R>) -> Result<Vec<String>, Error> {
+    let mut events = Vec::new();
+    loop {
+        match p.read_event() {
+            Ok(Event::StartElement(el)) => {
+                let mut event = format!("<{}", el.name);
+                for attr in el.attributes() {
+                    event.push_str(&format!(" {}='{}'", attr.name, attr.value));
+                }
+                events.push(event + ">");
+            }
+            Ok(Event::EndElement(el)) => events.push(format!("</{}>", el.name)),
+            Ok(Event::Characters(c)) => events.push(c.to_owned()),
+            Ok(Event::Reference(r)) => match r.resolved {
+                Some(resolved) => events.push(format!("ref {} {}", r.raw, resolved)),
+                None => events.push(format!("ref {}", r.raw)),
+            },
+            Ok(Event::EndDocument) => return Ok(events),
+            Ok(_) => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+pub fn parse(data: &str) -> Result<Vec<String>, Error> {
+    content_events(Parser::from_reader(data.as_bytes()))
+}
+
+pub fn first_error<R: Read>(mut p: Parser<R>) -> Error {
+    loop {
+        match p.read_event() {
+            Ok(Event::EndDocument) => panic!("document should be rejected"),
+            Ok(_) => {}
+            Err(err) => return err,
+        }
+    }
+}
+
+pub fn first_error_kind(data: &str) -> ErrorKind {
+    match first_error(Parser::from_reader(data.as_bytes())) {
+        Error::Parsing { kind, .. } => kind,
+        err => panic!("expected a parsing error, found: {:?}", err),
+    }
+}
@@ -0,0 +1,122 @@
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, options, Parser, ParserBuilder},
+    validation::ValidityErrorKind,
+    Event,
+};
+
+// name, value and specified flag of the attributes of each element
+fn element_attributes(data: &str) -> Result<Vec<Vec<(String, String, bool)>>, ErrorKind> {
+    let mut p = Parser::from_reader(data.as_bytes());
+    let mut elements = Vec::new();
+    loop {
+        match p.read_event() {
+            Ok(Event::StartElement(el)) => elements.push(
+                el.attributes()
+                    .map(|a| (a.name.to_owned(), a.value.to_owned(), a.specified))
+                    .collect(),
+            ),
+            Ok(Event::EndDocument) => return Ok(elements),
+            Ok(_) => {}
+            Err(err) => return Err(err.kind().cloned().expect("parsing error")),
+        }
+    }
+}
+
+fn attr(name: &str, value: &str, specified: bool) -> (String, String, bool) {
+    (name.to_owned(), value.to_owned(), specified)
+}
+
+const DTD: &str = r#"<!DOCTYPE list [
+<!ENTITY active "active">
+<!ATTLIST item status CDATA "&active;"
+               id ID #IMPLIED
+               kind (a|b) " a ">
+<!ATTLIST item version CDATA #FIXED "1.0" status CDATA "ignored">
+]>"#;
+
+#[test]
+fn test_default_attributes() {
+    let data = format!(
+        "{}<list><item/><item status='old' kind='b  '/><item version='1.0'/></list>",
+        DTD
+    );
+    assert_eq!(
+        element_attributes(&data).unwrap(),
+        vec![
+            vec![],
+            vec![
+                attr("status", "active", false),
+                attr("kind", "a", false),
+                attr("version", "1.0", false),
+            ],
+            vec![
+                attr("status", "old", true),
+                attr("kind", "b", true),
+                attr("version", "1.0", false),
+            ],
+            vec![
+                attr("version", "1.0", true),
+                attr("status", "active", false),
+                attr("kind", "a", false),
+            ],
+        ]
+    );
+}
+
+#[test]
+fn test_fixed_attribute_mismatch() {
+    // a validity error, the document is well-formed
+    let data = format!("{}<list><item version='2.0'/></list>", DTD);
+
+    // not checked without validation
+    let mut p = Parser::from_reader(data.as_bytes());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert!(p.validity_errors().is_empty());
+    assert_eq!(
+        element_attributes(&data).unwrap(),
+        vec![
+            vec![],
+            vec![
+                attr("version", "2.0", true),
+                attr("status", "active", false),
+                attr("kind", "a", false),
+            ],
+        ]
+    );
+
+    let mut p = ParserBuilder::from_reader(data.as_bytes())
+        .validation(options::Validation::Enabled)
+        .build();
+    while p.read_event().unwrap() != Event::EndDocument {}
+    let errors: Vec<_> = p.validity_errors().iter().map(|e| &e.kind).collect();
+    assert!(
+        errors.contains(&&ValidityErrorKind::FixedAttributeMismatch {
+            element: "item".to_owned(),
+            name: "version".to_owned(),
+            expected: "1.0".to_owned(),
+            found: "2.0".to_owned(),
+        })
+    );
+}
+
+#[test]
+fn test_default_namespace_declaration() {
+    let data =
+        "<!DOCTYPE p:root [<!ATTLIST p:root xmlns:p CDATA #FIXED 'urn:p' p:a CDATA 'x'>]><p:root/>";
+    let mut p = Parser::from_reader(data.as_bytes());
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                assert_eq!(el.namespace, "urn:p");
+                let attributes: Vec<_> = el
+                    .attributes()
+                    .map(|a| (a.local_name, a.namespace, a.specified))
+                    .collect();
+                assert_eq!(attributes, vec![("p", "", false), ("a", "urn:p", false)]);
+                break;
+            }
+            Event::EndDocument => panic!("no element"),
+            _ => {}
+        }
+    }
+}
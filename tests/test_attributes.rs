@@ -0,0 +1,63 @@
+use xml_oxide::sax::{parser::Parser, Event};
+
+fn root_attributes(data: &str) -> Vec<(String, String, String)> {
+    let mut p = Parser::from_reader(data.as_bytes());
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                return el
+                    .attributes()
+                    .map(|a| {
+                        (
+                            a.name.to_owned(),
+                            a.value.to_owned(),
+                            a.raw_value.to_owned(),
+                        )
+                    })
+                    .collect();
+            }
+            Event::EndDocument => panic!("no element in: {}", data),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_attribute_value_normalization() {
+    let attributes = root_attributes(
+        "<root a='x&amp;y &lt;&#60;&#x3E;' b=\"1\t2\n3\r\n4\" c='&#10;&#9;' d='plain'/>",
+    );
+
+    assert_eq!(
+        attributes,
+        vec![
+            (
+                "a".to_owned(),
+                "x&y <<>".to_owned(),
+                "x&amp;y &lt;&#60;&#x3E;".to_owned()
+            ),
+            (
+                "b".to_owned(),
+                "1 2 3 4".to_owned(),
+                "1\t2\n3\n4".to_owned()
+            ),
+            ("c".to_owned(), "\n\t".to_owned(), "&#10;&#9;".to_owned()),
+            ("d".to_owned(), "plain".to_owned(), "plain".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_namespace_declaration_is_normalized() {
+    let mut p = Parser::from_reader("<p:root xmlns:p='urn:a&amp;b'/>".as_bytes());
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                assert_eq!(el.namespace, "urn:a&b");
+                break;
+            }
+            Event::EndDocument => panic!("no element"),
+            _ => {}
+        }
+    }
+}
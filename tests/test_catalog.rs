@@ -0,0 +1,124 @@
+use xml_oxide::sax::{
+    catalog::{CatalogError, CatalogResolver},
+    parser::ParserBuilder,
+    resolver::MemoryResolver,
+    Event,
+};
+
+const CATALOG: &str = "tests/xml_files/catalog/catalog.xml";
+
+#[test]
+fn test_catalog_entries() {
+    let catalog = CatalogResolver::from_file(CATALOG).unwrap();
+    let external_id = |public_id, system_id| catalog.resolve_external_id(public_id, system_id);
+
+    assert_eq!(
+        external_id(
+            Some(" -//Example//DTD  Article V1.0//EN"),
+            Some("article.dtd")
+        )
+        .as_deref(),
+        Some("tests/xml_files/catalog/dtd/article.dtd")
+    );
+    assert_eq!(
+        external_id(None, Some("http://example.com/dtd/note.ent")).as_deref(),
+        Some("tests/xml_files/catalog/dtd/note.ent")
+    );
+    assert_eq!(
+        external_id(None, Some("http://example.com/docbook/x/y.ent")).as_deref(),
+        Some("tests/xml_files/catalog/docbook/./x/y.ent")
+    );
+    // prefer="system" ignores the public identifier when there is a system identifier
+    assert_eq!(
+        external_id(Some("-//Example//ENTITIES Symbols//EN"), Some("other.ent")),
+        None
+    );
+    assert_eq!(
+        external_id(Some("-//Example//ENTITIES Symbols//EN"), None).as_deref(),
+        Some("tests/xml_files/catalog/docbook/symbols.ent")
+    );
+    // from the next catalog, which refers back to the first one
+    assert_eq!(
+        external_id(Some("-//Example//ENTITIES More//EN"), Some("more.ent")).as_deref(),
+        Some("tests/xml_files/catalog/more/more.ent")
+    );
+    assert_eq!(
+        catalog
+            .resolve_uri("http://example.com/legal.ent")
+            .as_deref(),
+        Some("tests/xml_files/catalog/dtd/legal.ent")
+    );
+    assert_eq!(
+        external_id(Some("-//Unknown//EN"), Some("unknown.dtd")),
+        None
+    );
+    // OASIS XML Catalogs §6.4, URNs are unwrapped into public identifiers
+    let article = Some("tests/xml_files/catalog/dtd/article.dtd");
+    let urn = "urn:publicid:-:Example:DTD+Article+V1.0:EN";
+    assert_eq!(external_id(None, Some(urn)).as_deref(), article);
+    assert_eq!(external_id(Some(urn), None).as_deref(), article);
+    assert_eq!(
+        external_id(Some("-//Example//DTD Article V1.0//EN"), Some(urn)).as_deref(),
+        article
+    );
+}
+
+#[test]
+fn test_catalog_resolver() {
+    let data = r#"<!DOCTYPE article PUBLIC "-//Example//DTD Article V1.0//EN"
+  "http://example.com/dtd/article.dtd">
+<article>&note;, &symbols;, &more;, &legal;</article>"#;
+    let resolver = CatalogResolver::from_file(CATALOG).unwrap();
+
+    let mut p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) if el.name == "article" => {
+                let lang = el.attributes().find(|a| a.name == "lang").unwrap();
+                assert_eq!(lang.value, "en");
+            }
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "a note, rewritten symbols, more, legal");
+}
+
+#[test]
+fn test_catalog_fallback() {
+    let data = "<!DOCTYPE doc [<!ENTITY e SYSTEM 'e.ent'>]><doc>&e;</doc>";
+    let mut fallback = MemoryResolver::new();
+    fallback.insert("e.ent", "from the fallback");
+    let resolver = CatalogResolver::from_file(CATALOG)
+        .unwrap()
+        .with_fallback(fallback);
+
+    let mut p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "from the fallback");
+}
+
+#[test]
+fn test_catalog_errors() {
+    assert!(matches!(
+        CatalogResolver::from_file("tests/xml_files/catalog/none.xml"),
+        Err(CatalogError::Io { .. })
+    ));
+    assert!(matches!(
+        CatalogResolver::from_file("tests/xml_files/catalog/dtd/note.ent"),
+        Err(CatalogError::Parsing { .. })
+    ));
+}
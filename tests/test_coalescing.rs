@@ -0,0 +1,91 @@
+mod common;
+
+use std::io::Read;
+
+use common::OneByteReader;
+use xml_oxide::sax::{
+    parser::{options, Parser, ParserBuilder},
+    Event,
+};
+
+fn coalescing_parser(
+    data: &str,
+    include_cdata: bool,
+    max_size: usize,
+) -> Parser<OneByteReader<'_>> {
+    ParserBuilder::from_reader(OneByteReader(data.as_bytes()))
+        .text_coalescing(options::TextCoalescing::Enabled {
+            include_cdata,
+            max_size,
+        })
+        .build()
+}
+
+fn text_events<R: Read>(mut p: Parser<R>) -> Vec<String> {
+    let mut events = Vec::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) => events.push(c.to_owned()),
+            Event::StartCdataSection => events.push("cdata ".to_owned()),
+            Event::Cdata(c) => events.last_mut().unwrap().push_str(c),
+            Event::StartElement(el) => events.push(format!("<{}>", el.name)),
+            Event::Comment(_) => events.push("comment".to_owned()),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    events
+}
+
+#[test]
+fn test_coalescing() {
+    let data = "<root>ab &amp; c\r\n<![CDATA[d<e]]>f<x/>g<!--c-->h<![CDATA[]]></root>";
+
+    assert_eq!(
+        text_events(coalescing_parser(data, true, 1024)),
+        vec!["<root>", "ab & c\nd<ef", "<x>", "g", "comment", "h"]
+    );
+    assert_eq!(
+        text_events(coalescing_parser(data, false, 1024)),
+        vec![
+            "<root>",
+            "ab & c\n",
+            "cdata d<e",
+            "f",
+            "<x>",
+            "g",
+            "comment",
+            "h",
+            "cdata "
+        ]
+    );
+}
+
+#[test]
+fn test_coalescing_max_size() {
+    let text = "0123456789ğ".repeat(10);
+    let data = format!("<root>{}</root>", text);
+
+    let events = text_events(coalescing_parser(&data, false, 16));
+    assert!(events.iter().skip(1).all(|c| c.len() <= 16));
+    assert_eq!(events[1..].concat(), text);
+
+    // a single large read is split as well
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .text_coalescing(options::TextCoalescing::Enabled {
+            include_cdata: false,
+            max_size: 16,
+        })
+        .build();
+    let events = text_events(p);
+    assert!(events.len() > 2);
+    assert!(events.iter().skip(1).all(|c| c.len() <= 16));
+    assert_eq!(events[1..].concat(), text);
+}
+
+#[test]
+fn test_coalescing_min_size() {
+    // raised to 4 bytes, a character is never split
+    let events = text_events(coalescing_parser("<root>ab𝄞c</root>", false, 1));
+    assert_eq!(events, vec!["<root>", "ab", "𝄞", "c"]);
+}
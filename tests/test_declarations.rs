@@ -0,0 +1,101 @@
+use xml_oxide::sax::{
+    declaration::{
+        AttDef, AttType, AttlistDecl, ContentParticle, ContentSpec, DefaultDecl, ElementDecl,
+        EntityDecl, EntityDef, ExternalId, NotationDecl, ParticleKind, Repetition,
+    },
+    parser::{options, Parser, ParserBuilder},
+    Event, OwnedEvent,
+};
+
+const DATA: &str = r#"<!DOCTYPE doc [
+<!ELEMENT doc (item)*>
+<!ATTLIST doc version CDATA #FIXED "1.0">
+<!-- comment -->
+<!ENTITY % pe "<!ELEMENT x ANY>">
+<!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+<!NOTATION gif SYSTEM "viewer">
+]>
+<doc/>"#;
+
+fn owned_events<R: std::io::Read>(p: Parser<R>) -> Vec<OwnedEvent> {
+    p.into_owned_events().collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_declarations_reported() {
+    let p = ParserBuilder::from_reader(DATA.as_bytes())
+        .markup_declarations(options::MarkupDeclarations::Report)
+        .build();
+    let events = owned_events(p);
+
+    assert!(matches!(events[1], OwnedEvent::DocumentTypeDeclaration(_)));
+    assert_eq!(
+        events[2..7],
+        [
+            OwnedEvent::ElementDecl(ElementDecl {
+                name: "doc".to_owned(),
+                content_spec: ContentSpec::Children(ContentParticle {
+                    kind: ParticleKind::Seq(vec![ContentParticle {
+                        kind: ParticleKind::Name("item".to_owned()),
+                        repetition: Repetition::Once,
+                    }]),
+                    repetition: Repetition::ZeroOrMore,
+                }),
+            }),
+            OwnedEvent::AttlistDecl(AttlistDecl {
+                element: "doc".to_owned(),
+                attributes: vec![AttDef {
+                    name: "version".to_owned(),
+                    att_type: AttType::Cdata,
+                    default: DefaultDecl::Fixed("1.0".to_owned()),
+                }],
+            }),
+            OwnedEvent::EntityDecl(EntityDecl {
+                name: "pe".to_owned(),
+                parameter: true,
+                definition: EntityDef::Internal("<!ELEMENT x ANY>".to_owned()),
+            }),
+            OwnedEvent::EntityDecl(EntityDecl {
+                name: "logo".to_owned(),
+                parameter: false,
+                definition: EntityDef::External {
+                    external_id: ExternalId {
+                        public_id: None,
+                        system_id: "logo.gif".to_owned(),
+                    },
+                    notation: Some("gif".to_owned()),
+                },
+            }),
+            OwnedEvent::NotationDecl(NotationDecl {
+                name: "gif".to_owned(),
+                public_id: None,
+                system_id: Some("viewer".to_owned()),
+            }),
+        ]
+    );
+    assert!(matches!(events[8], OwnedEvent::StartElement(_)));
+}
+
+#[test]
+fn test_declarations_span() {
+    let mut p = ParserBuilder::from_reader(DATA.as_bytes())
+        .markup_declarations(options::MarkupDeclarations::Report)
+        .build();
+    p.read_event().unwrap();
+    let (_, doctype_span) = p.read_event_with_span().unwrap();
+    let (event, span) = p.read_event_with_span().unwrap();
+    assert!(matches!(event, Event::ElementDecl(_)));
+    assert_eq!(span, doctype_span);
+}
+
+#[test]
+fn test_declarations_skipped_by_default() {
+    let events = owned_events(Parser::from_reader(DATA.as_bytes()));
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        OwnedEvent::ElementDecl(_)
+            | OwnedEvent::AttlistDecl(_)
+            | OwnedEvent::EntityDecl(_)
+            | OwnedEvent::NotationDecl(_)
+    )));
+}
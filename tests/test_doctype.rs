@@ -0,0 +1,80 @@
+use xml_oxide::sax::{parser::Parser, DocType, Event, OwnedDocType, OwnedEvent};
+
+fn doctype(data: &str) -> OwnedDocType {
+    let mut p = Parser::from_reader(data.as_bytes());
+    loop {
+        match p.read_event().unwrap() {
+            Event::DocumentTypeDeclaration(doctype) => return (&doctype).into(),
+            Event::EndDocument => panic!("no doctype in: {}", data),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_doctype_public() {
+    let d = doctype(
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" 'http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd'><html/>",
+    );
+    assert_eq!(d.name, "html");
+    assert_eq!(
+        d.public_id.as_deref(),
+        Some("-//W3C//DTD XHTML 1.0 Strict//EN")
+    );
+    assert_eq!(
+        d.system_id.as_deref(),
+        Some("http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd")
+    );
+    assert_eq!(d.internal_subset, None);
+}
+
+#[test]
+fn test_doctype_system_and_internal_subset() {
+    let d = doctype(
+        "<?xml version='1.0'?>\r\n<!DOCTYPE greeting SYSTEM \"hello.dtd\" [\r\n<!ELEMENT greeting (#PCDATA)>\r\n]><greeting/>",
+    );
+    assert_eq!(d.name, "greeting");
+    assert_eq!(d.public_id, None);
+    assert_eq!(d.system_id.as_deref(), Some("hello.dtd"));
+    assert_eq!(
+        d.internal_subset.as_deref(),
+        Some("\n<!ELEMENT greeting (#PCDATA)>\n")
+    );
+}
+
+#[test]
+fn test_doctype_without_external_id() {
+    let mut p = Parser::from_reader("<!DOCTYPE root [<!ENTITY e 'x'>]><root/>".as_bytes());
+    loop {
+        match p.read_event().unwrap() {
+            Event::DocumentTypeDeclaration(doctype) => {
+                assert_eq!(
+                    doctype,
+                    DocType {
+                        name: "root",
+                        public_id: None,
+                        system_id: None,
+                        internal_subset: Some("<!ENTITY e 'x'>"),
+                    }
+                );
+                break;
+            }
+            Event::EndDocument => panic!("no doctype"),
+            _ => {}
+        }
+    }
+
+    let events: Vec<OwnedEvent> = Parser::from_reader("<!DOCTYPE root><root/>".as_bytes())
+        .into_owned_events()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        events[1],
+        OwnedEvent::DocumentTypeDeclaration(OwnedDocType {
+            name: "root".to_owned(),
+            public_id: None,
+            system_id: None,
+            internal_subset: None,
+        })
+    );
+}
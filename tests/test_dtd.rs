@@ -0,0 +1,172 @@
+use xml_oxide::sax::{
+    declaration::{AttType, ContentSpec, DefaultDecl, EntityDef, MarkupDecl},
+    parser::{error::ErrorKind, options, Parser, ParserBuilder},
+    resolver::MemoryResolver,
+    Event,
+};
+
+const DOC: &str = r#"<!DOCTYPE book SYSTEM "book.dtd" [
+<!ENTITY % draft "INCLUDE">
+<!ENTITY title "Internal title">
+]>
+<book>&title; &chapters;</book>"#;
+
+const BOOK_DTD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!ENTITY % common SYSTEM "common.ent">
+%common;
+<!ENTITY % final "IGNORE">
+<![%draft;[
+  <!ATTLIST book status CDATA "draft">
+]]>
+<![%final;[
+  <!ATTLIST book status CDATA "final">
+]]>
+<!ELEMENT book (#PCDATA)>
+<!ATTLIST book %id.attr;>
+<!ENTITY title "External title">
+<!ENTITY chapters "%chapter.count; chapters">"#;
+
+const COMMON_ENT: &str = r#"<!ENTITY % id.attr "id ID #IMPLIED">
+<!ENTITY % chapter.count "3">"#;
+
+fn resolver() -> MemoryResolver {
+    let mut resolver = MemoryResolver::new();
+    resolver
+        .insert("book.dtd", BOOK_DTD)
+        .insert("common.ent", COMMON_ENT);
+    resolver
+}
+
+#[test]
+fn test_external_subset() {
+    let mut p = ParserBuilder::from_reader(DOC.as_bytes())
+        .entity_resolver(resolver())
+        .build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                let attributes: Vec<_> = el.attributes().map(|a| (a.name, a.value)).collect();
+                assert_eq!(attributes, vec![("status", "draft")]);
+            }
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "Internal title 3 chapters");
+
+    let dtd = p.dtd().unwrap();
+    assert_eq!(dtd.name, "book");
+    assert_eq!(dtd.system_id.as_deref(), Some("book.dtd"));
+    assert_eq!(
+        dtd.element("book").unwrap().content_spec,
+        ContentSpec::Mixed(vec![])
+    );
+    let attributes: Vec<_> = dtd
+        .attributes("book")
+        .iter()
+        .map(|def| (def.name.as_str(), def.att_type.clone(), def.default.clone()))
+        .collect();
+    assert_eq!(
+        attributes,
+        vec![
+            (
+                "status",
+                AttType::Cdata,
+                DefaultDecl::Value("draft".to_owned())
+            ),
+            ("id", AttType::Id, DefaultDecl::Implied),
+        ]
+    );
+    // the internal subset comes first and binds
+    assert_eq!(
+        dtd.entity("title").unwrap().definition,
+        EntityDef::Internal("Internal title".to_owned())
+    );
+    assert_eq!(
+        dtd.parameter_entity("chapter.count").unwrap().definition,
+        EntityDef::Internal("3".to_owned())
+    );
+    assert_eq!(dtd.declarations.len(), 11);
+}
+
+#[test]
+fn test_dtd_without_resolver() {
+    let mut p = Parser::from_reader(DOC.as_bytes());
+    loop {
+        if p.read_event().unwrap() == Event::EndDocument {
+            break;
+        }
+    }
+    let dtd = p.dtd().unwrap();
+    assert_eq!(dtd.declarations.len(), 2);
+    assert!(dtd.element("book").is_none());
+}
+
+#[test]
+fn test_reported_declarations() {
+    let mut p = ParserBuilder::from_reader(DOC.as_bytes())
+        .entity_resolver(resolver())
+        .markup_declarations(options::MarkupDeclarations::Report)
+        .build();
+    let mut declarations = 0;
+    loop {
+        match p.read_event().unwrap() {
+            Event::ElementDecl(_)
+            | Event::AttlistDecl(_)
+            | Event::EntityDecl(_)
+            | Event::NotationDecl(_) => declarations += 1,
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(declarations, p.dtd().unwrap().declarations.len());
+    assert!(matches!(
+        p.dtd().unwrap().declarations[0],
+        MarkupDecl::Entity(_)
+    ));
+}
+
+#[test]
+fn test_parameter_entity_errors() {
+    let parse = |data: &str| {
+        let mut p = ParserBuilder::from_reader(data.as_bytes())
+            .entity_resolver(resolver())
+            .build();
+        loop {
+            match p.read_event() {
+                Ok(Event::EndDocument) => return Ok(()),
+                Ok(_) => {}
+                Err(err) => return Err(err.kind().cloned().expect("parsing error")),
+            }
+        }
+    };
+
+    // PEs in Internal Subset WFC
+    assert!(parse("<!DOCTYPE a [<!ENTITY % e 'x'><!ELEMENT a %e;>]><a/>").is_err());
+    assert_eq!(
+        parse("<?xml version='1.0' standalone='yes'?><!DOCTYPE a [%undeclared;]><a/>"),
+        Err(ErrorKind::UndeclaredEntity {
+            name: "%undeclared".to_owned()
+        })
+    );
+    // not read, the declarations after it are not processed
+    assert_eq!(
+        parse("<!DOCTYPE a [%undeclared;<!ENTITY e 'x'>]><a>&e;</a>"),
+        Ok(())
+    );
+    assert!(parse("<!DOCTYPE a [<!ENTITY % e '<![INCLUDE[ <!ELEMENT a ANY>'>%e;]><a/>").is_err());
+}
+
+#[test]
+fn test_declarations_after_skipped_reference() {
+    let data =
+        "<!DOCTYPE a [%undeclared;<!ENTITY e 'x'><!ATTLIST a b CDATA 'c'><!ELEMENT a ANY>]><a/>";
+    let mut p = Parser::from_reader(data.as_bytes());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    let dtd = p.dtd().unwrap();
+    assert!(dtd.entity("e").is_none());
+    assert!(dtd.attributes("a").is_empty());
+    assert!(dtd.element("a").is_some());
+}
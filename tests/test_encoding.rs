@@ -0,0 +1,334 @@
+mod common;
+
+use std::io::Read;
+
+use common::{first_error, OneByteReader};
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, options, Parser, ParserBuilder},
+    resolver::MemoryResolver,
+    Event, OwnedEvent,
+};
+
+fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect()
+}
+
+fn utf16be(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .flat_map(|unit| unit.to_be_bytes())
+        .collect()
+}
+
+// text is coalesced, it is split at read boundaries otherwise
+fn events<R: Read>(reader: R) -> Vec<OwnedEvent> {
+    ParserBuilder::from_reader(reader)
+        .text_coalescing(options::TextCoalescing::Enabled {
+            include_cdata: false,
+            max_size: 1024,
+        })
+        .build()
+        .into_owned_events()
+        .map(|event| event.unwrap())
+        .filter(|event| {
+            matches!(
+                event,
+                OwnedEvent::StartElement(_) | OwnedEvent::Characters(_) | OwnedEvent::EndElement(_)
+            )
+        })
+        .collect()
+}
+
+const DOC: &str = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n<a t='ç'>kürk 𝄞</a>";
+
+#[test]
+fn test_utf16() {
+    let expected = events(DOC.replace("UTF-16", "UTF-8").as_bytes());
+    assert_eq!(expected.len(), 3);
+
+    for input in [
+        utf16le(&format!("\u{FEFF}{}", DOC)),
+        utf16be(&format!("\u{FEFF}{}", DOC)),
+        // detected from `<?` without a byte order mark
+        utf16le(DOC),
+        utf16be(DOC),
+    ] {
+        assert_eq!(events(input.as_slice()), expected);
+        assert_eq!(events(OneByteReader(input.as_slice())), expected);
+    }
+
+    let input = utf16le(&format!("\u{FEFF}{}", DOC));
+    let mut p = Parser::from_reader(input.as_slice());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert_eq!(p.xml_declaration().unwrap().encoding, Some("UTF-16"));
+}
+
+#[test]
+fn test_utf8_byte_order_mark() {
+    let input = "\u{FEFF}<a>text</a>";
+    let mut p = Parser::from_reader(input.as_bytes());
+    loop {
+        if let Event::StartElement(_) = p.read_event().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(p.position().offset, 3);
+    assert_eq!(p.position().column, 1);
+}
+
+#[test]
+fn test_positions_in_input_bytes() {
+    // `</b>` starts after the BOM and 5 characters, one of them a surrogate pair
+    let input = utf16le("\u{FEFF}<a>ç𝄞</b>");
+    for err in [
+        first_error(Parser::from_reader(input.as_slice())),
+        first_error(Parser::from_reader(OneByteReader(input.as_slice()))),
+    ] {
+        assert!(matches!(
+            err.kind(),
+            Some(ErrorKind::MismatchedEndTag { .. })
+        ));
+        assert_eq!(err.position().offset, 2 + 4 * 2 + 4);
+        assert_eq!(err.position().line, 1);
+        assert_eq!(err.position().column, 6);
+    }
+
+    let input = utf16be("\u{FEFF}<a>\n<b/></a>");
+    let mut p = Parser::from_reader(input.as_slice());
+    loop {
+        if let Event::StartElement(el) = p.read_event().unwrap() {
+            if el.name == "b" {
+                break;
+            }
+        }
+    }
+    assert_eq!(p.position().offset, 2 + 4 * 2);
+    assert_eq!(p.position().line, 2);
+}
+
+#[test]
+fn test_encoding_errors() {
+    let err = first_error(Parser::from_reader(
+        "<?xml version='1.0' encoding='UTF-16'?><a/>".as_bytes(),
+    ));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::EncodingMismatch {
+            declared: "UTF-16".to_owned(),
+            detected: "UTF-8",
+        })
+    );
+
+    let input = utf16le("\u{FEFF}<?xml version='1.0' encoding='UTF-8'?><a/>");
+    let err = first_error(Parser::from_reader(input.as_slice()));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::EncodingMismatch {
+            declared: "UTF-8".to_owned(),
+            detected: "UTF-16LE",
+        })
+    );
+    assert_eq!(err.position().offset, 2);
+
+    let input = b"\x00\x00\x00<\x00\x00\x00a\x00\x00\x00/\x00\x00\x00>";
+    let err = first_error(Parser::from_reader(&input[..]));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::UnsupportedEncoding {
+            encoding: "UCS-4".to_owned(),
+        })
+    );
+
+    // unpaired surrogate
+    let mut input = utf16le("\u{FEFF}<a>\nb");
+    input.extend_from_slice(&[0x00, 0xD8]);
+    input.extend_from_slice(&utf16le("</a>"));
+    for err in [
+        first_error(Parser::from_reader(input.as_slice())),
+        first_error(Parser::from_reader(OneByteReader(input.as_slice()))),
+    ] {
+        assert_eq!(
+            err.kind(),
+            Some(&ErrorKind::InvalidEncoding {
+                encoding: "UTF-16LE"
+            })
+        );
+        assert_eq!(err.position().offset, 12);
+        assert_eq!(err.position().line, 2);
+        assert_eq!(err.position().column, 2);
+    }
+
+    // undefined in windows-1252, also in the input that is decoded again
+    let doc = b"<?xml version='1.0' encoding='windows-1252'?><a>\x81</a>";
+    for err in [
+        first_error(Parser::from_reader(&doc[..])),
+        first_error(Parser::from_reader(OneByteReader(&doc[..]))),
+    ] {
+        assert_eq!(
+            err.kind(),
+            Some(&ErrorKind::InvalidEncoding {
+                encoding: "windows-1252"
+            })
+        );
+        assert_eq!(err.position().offset, 48);
+        assert_eq!(err.position().column, 49);
+    }
+}
+
+#[test]
+fn test_utf16_external_entity() {
+    let doc = r#"<!DOCTYPE a [<!ENTITY e SYSTEM "e.xml">]><a>&e;</a>"#;
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("e.xml", utf16be("\u{FEFF}<?xml encoding='UTF-16'?>text 𝄞"));
+    let mut p = ParserBuilder::from_reader(doc.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "text 𝄞");
+}
+
+#[test]
+fn test_single_byte_encodings() {
+    let doc = b"<?xml version='1.0' encoding='ISO-8859-1'?>\n<a t='\xE7'>k\xFCrk</a>";
+    let expected = events("<?xml version='1.0'?>\n<a t='ç'>kürk</a>".as_bytes());
+    assert_eq!(events(&doc[..]), expected);
+    assert_eq!(events(OneByteReader(&doc[..])), expected);
+
+    let doc = b"<?xml version='1.0' encoding='windows-1252'?><a>\x80 \x93q\x94</a>";
+    assert_eq!(events(&doc[..]), events("<a>€ “q”</a>".as_bytes()));
+
+    // names are case insensitive
+    let doc = b"<?xml version='1.0' encoding='iso-8859-15'?><a>\xA4</a>";
+    assert_eq!(events(&doc[..]), events("<a>€</a>".as_bytes()));
+
+    // offsets count bytes of the input
+    let doc = b"<?xml version='1.0' encoding='ISO-8859-1'?><a>\xE7\xE7</b>";
+    let err = first_error(Parser::from_reader(&doc[..]));
+    assert!(matches!(
+        err.kind(),
+        Some(ErrorKind::MismatchedEndTag { .. })
+    ));
+    assert_eq!(err.position().offset, 43 + 3 + 2);
+}
+
+#[test]
+fn test_single_byte_errors() {
+    // undefined in windows-1252
+    let doc = b"<?xml version='1.0' encoding='windows-1252'?><a>\x81</a>";
+    let err = first_error(Parser::from_reader(&doc[..]));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::InvalidEncoding {
+            encoding: "windows-1252"
+        })
+    );
+
+    let doc = b"<?xml version='1.0' encoding='US-ASCII'?><a>\xE7</a>";
+    let err = first_error(Parser::from_reader(&doc[..]));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::InvalidEncoding {
+            encoding: "US-ASCII"
+        })
+    );
+
+    let doc = "<?xml version='1.0' encoding='KOI8-R'?><a/>";
+    let err = first_error(Parser::from_reader(doc.as_bytes()));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::UnsupportedEncoding {
+            encoding: "KOI8-R".to_owned(),
+        })
+    );
+
+    // contradicts the byte order mark
+    let doc = "\u{FEFF}<?xml version='1.0' encoding='ISO-8859-1'?><a/>";
+    let err = first_error(Parser::from_reader(doc.as_bytes()));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::EncodingMismatch {
+            declared: "ISO-8859-1".to_owned(),
+            detected: "UTF-8",
+        })
+    );
+
+    let input = utf16le("\u{FEFF}<?xml version='1.0' encoding='windows-1252'?><a/>");
+    let err = first_error(Parser::from_reader(input.as_slice()));
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::EncodingMismatch {
+            declared: "windows-1252".to_owned(),
+            detected: "UTF-16LE",
+        })
+    );
+}
+
+#[test]
+fn test_encoding_override() {
+    // the declaration is not checked
+    let doc = b"<?xml version='1.0' encoding='UTF-8'?><a>\xE7</a>";
+    let builder = ParserBuilder::from_reader(&doc[..]).encoding("ISO-8859-1");
+    let mut p = builder.build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "ç");
+
+    // without a declaration
+    let doc = b"<a>\xA4</a>";
+    let mut p = ParserBuilder::from_reader(&doc[..])
+        .encoding("ISO-8859-15")
+        .build();
+    while p.read_event().unwrap() != Event::Characters("€") {}
+
+    // the byte order mark of UTF-16 is skipped
+    let input = utf16be("\u{FEFF}<a/>");
+    let mut p = ParserBuilder::from_reader(input.as_slice())
+        .encoding("UTF-16")
+        .build();
+    while p.read_event().unwrap() != Event::EndDocument {}
+
+    let err = first_error(
+        ParserBuilder::from_reader("<a/>".as_bytes())
+            .encoding("EBCDIC-US")
+            .build(),
+    );
+    assert_eq!(
+        err.kind(),
+        Some(&ErrorKind::UnsupportedEncoding {
+            encoding: "EBCDIC-US".to_owned(),
+        })
+    );
+}
+
+#[test]
+fn test_single_byte_external_entity() {
+    let doc = r#"<!DOCTYPE a [<!ENTITY e SYSTEM "e.xml">]><a>&e;</a>"#;
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("e.xml", &b"<?xml encoding='ISO-8859-1'?>gar\xE7on"[..]);
+    let mut p = ParserBuilder::from_reader(doc.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    let mut text = String::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) => text.push_str(c),
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(text, "garçon");
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::io::Read;
+
+use common::{content_events, parse, OneByteReader};
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, Parser},
+    Event,
+};
+
+const DTD: &str = r#"<!DOCTYPE root [
+<!ENTITY company "ACME">
+<!ENTITY full "&company; <b>&lt;Corp&gt;</b>">
+<!ENTITY company "first declaration is binding">
+<!ENTITY lf "a&#10;b">
+]>"#;
+
+#[test]
+fn test_entity_expansion_in_content() {
+    let content = "<root>x &full; y</root>";
+    let expected = vec![
+        "<root>", "x ACME ", "<b>", "<Corp>", "</b>", " y", "</root>",
+    ];
+    assert_eq!(parse(&format!("{}{}", DTD, content)).unwrap(), expected);
+
+    let reader = DTD.as_bytes().chain(OneByteReader(content.as_bytes()));
+    // text can be split at read boundaries
+    let events = content_events(Parser::from_reader(reader)).unwrap();
+    assert_eq!(events.concat(), expected.concat());
+}
+
+#[test]
+fn test_entity_expansion_in_attribute_value() {
+    let data = format!(
+        "{}<root a='&full;' b='&lf;'/>",
+        DTD.replace("<b>", "").replace("</b>", "")
+    );
+    assert_eq!(
+        parse(&data).unwrap(),
+        vec!["<root a='ACME <Corp>' b='a b'>"]
+    );
+}
+
+#[test]
+fn test_entity_expansion_position() {
+    let data = format!("{}<root>&company;<x/></root>", DTD);
+    let mut p = Parser::from_reader(data.as_bytes());
+    loop {
+        if let (Event::StartElement(el), span) = p.read_event_with_span().unwrap() {
+            if el.name == "x" {
+                assert_eq!(&data[span.start.offset..span.end.offset], "<x/>");
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_entity_declared() {
+    assert_eq!(
+        parse("<root>&undeclared;</root>").unwrap_err().kind(),
+        Some(&ErrorKind::UndeclaredEntity {
+            name: "undeclared".to_owned()
+        })
+    );
+    assert_eq!(
+        parse("<root a='&undeclared;'/>").unwrap_err().kind(),
+        Some(&ErrorKind::UndeclaredEntity {
+            name: "undeclared".to_owned()
+        })
+    );
+
+    // declarations may be in the external subset
+    assert_eq!(
+        parse("<!DOCTYPE root SYSTEM 'root.dtd'><root>&undeclared;</root>").unwrap(),
+        vec!["<root>", "ref &undeclared;", "</root>"]
+    );
+    assert_eq!(
+        parse("<?xml version='1.0' standalone='yes'?><!DOCTYPE root SYSTEM 'root.dtd'><root>&undeclared;</root>").unwrap_err().kind(),
+        Some(&ErrorKind::UndeclaredEntity {
+            name: "undeclared".to_owned()
+        })
+    );
+}
+
+#[test]
+fn test_entity_no_recursion() {
+    let data = "<!DOCTYPE root [<!ENTITY a 'x&b;'><!ENTITY b '<b>&a;</b>'>]><root>&a;</root>";
+    assert_eq!(
+        parse(data).unwrap_err().kind(),
+        Some(&ErrorKind::RecursiveEntity {
+            name: "a".to_owned()
+        })
+    );
+
+    let data = "<!DOCTYPE root [<!ENTITY a '&a;'>]><root a='&a;'/>";
+    assert_eq!(
+        parse(data).unwrap_err().kind(),
+        Some(&ErrorKind::RecursiveEntity {
+            name: "a".to_owned()
+        })
+    );
+
+    // the same entity can be referenced again after its replacement text
+    let data = "<!DOCTYPE root [<!ENTITY a 'x'><!ENTITY b '&a;&a;'>]><root>&b;&b;</root>";
+    assert_eq!(parse(data).unwrap(), vec!["<root>", "xxxx", "</root>"]);
+}
+
+#[test]
+fn test_entity_balanced() {
+    for data in [
+        "<!DOCTYPE root [<!ENTITY e '<a>'>]><root>&e;</a></root>",
+        "<!DOCTYPE root [<!ENTITY e '</a>'>]><root><a>&e;</root>",
+        "<!DOCTYPE root [<!ENTITY e '</a><a>'>]><root><a>&e;</a></root>",
+        "<!DOCTYPE root [<!ENTITY e '<a'>]><root>&e;/></root>",
+        "<!DOCTYPE root [<!ENTITY e '<!--'>]><root>&e;--></root>",
+    ] {
+        assert_eq!(
+            parse(data).unwrap_err().kind(),
+            Some(&ErrorKind::UnbalancedEntity {
+                name: "e".to_owned()
+            }),
+            "{}",
+            data
+        );
+    }
+}
+
+#[test]
+fn test_entity_restrictions() {
+    let data = "<!DOCTYPE root [<!NOTATION gif SYSTEM 'gif'><!ENTITY pic SYSTEM 'a.gif' NDATA gif>]><root>&pic;</root>";
+    assert_eq!(
+        parse(data).unwrap_err().kind(),
+        Some(&ErrorKind::UnparsedEntityReference {
+            name: "pic".to_owned()
+        })
+    );
+
+    // external entities are not read, they are reported in content
+    let data = "<!DOCTYPE root [<!ENTITY ext SYSTEM 'ext.xml'>]><root>&ext;</root>";
+    assert_eq!(parse(data).unwrap(), vec!["<root>", "ref &ext;", "</root>"]);
+    let data = "<!DOCTYPE root [<!ENTITY ext SYSTEM 'ext.xml'>]><root a='&ext;'/>";
+    assert_eq!(
+        parse(data).unwrap_err().kind(),
+        Some(&ErrorKind::ExternalEntityInAttribute {
+            name: "ext".to_owned()
+        })
+    );
+
+    let data = "<!DOCTYPE root [<!ENTITY lt2 '&#60;'>]><root a='&lt2;'/>";
+    assert_eq!(
+        parse(data).unwrap_err().kind(),
+        Some(&ErrorKind::LessThanInAttributeValue {
+            name: "lt2".to_owned()
+        })
+    );
+}
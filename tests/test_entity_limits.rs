@@ -0,0 +1,119 @@
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, options, Parser, ParserBuilder},
+    Event,
+};
+
+// returns the text of the document or the first error
+fn parse<R: std::io::Read>(mut p: Parser<R>) -> Result<String, ErrorKind> {
+    let mut text = String::new();
+    loop {
+        match p.read_event() {
+            Ok(Event::StartElement(el)) => {
+                for attr in el.attributes() {
+                    text.push_str(attr.value);
+                }
+            }
+            Ok(Event::Characters(c)) => text.push_str(c),
+            Ok(Event::EndDocument) => return Ok(text),
+            Ok(_) => {}
+            Err(err) => return Err(err.kind().cloned().expect("parsing error")),
+        }
+    }
+}
+
+fn limit_error(result: Result<String, ErrorKind>) -> (String, &'static str) {
+    match result {
+        Err(ErrorKind::EntityExpansionLimit { name, limit }) => (name, limit),
+        other => panic!("expected an entity expansion limit error: {:?}", other),
+    }
+}
+
+const BILLION_LAUGHS: &str = r#"<!DOCTYPE lolz [
+<!ENTITY lol "lol">
+<!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+<!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+<!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+<!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+<!ENTITY lol5 "&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;">
+<!ENTITY lol6 "&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;">
+<!ENTITY lol7 "&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;">
+<!ENTITY lol8 "&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;">
+<!ENTITY lol9 "&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;">
+]>"#;
+
+#[test]
+fn test_billion_laughs() {
+    let data = format!("{}<lolz>&lol9;</lolz>", BILLION_LAUGHS);
+    let (_, limit) = limit_error(parse(Parser::from_reader(data.as_bytes())));
+    assert_eq!(limit, "amplification");
+
+    let data = format!("{}<lolz a='&lol9;'/>", BILLION_LAUGHS);
+    let (_, limit) = limit_error(parse(Parser::from_reader(data.as_bytes())));
+    assert_eq!(limit, "amplification");
+
+    // a few laughs are fine
+    let data = format!("{}<lolz a='&lol2;'>&lol2;</lolz>", BILLION_LAUGHS);
+    let text = parse(Parser::from_reader(data.as_bytes())).unwrap();
+    assert_eq!(text, "lol".repeat(200));
+}
+
+#[test]
+fn test_quadratic_blowup() {
+    let data = format!(
+        "<!DOCTYPE kaboom [<!ENTITY a \"{}\">]><kaboom>{}</kaboom>",
+        "a".repeat(20_000),
+        "&a;".repeat(1000)
+    );
+    let (name, _) = limit_error(parse(Parser::from_reader(data.as_bytes())));
+    assert_eq!(name, "a");
+
+    // without the amplification limit the total size stops it
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_limits(options::EntityLimits {
+            max_expanded_size: 4 * 1024 * 1024,
+            max_amplification: usize::MAX,
+            ..Default::default()
+        })
+        .build();
+    assert_eq!(limit_error(parse(p)), ("a".to_owned(), "expanded size"));
+}
+
+#[test]
+fn test_entity_nesting_depth() {
+    let mut dtd = String::from("<!DOCTYPE root [<!ENTITY e20 \"x\">");
+    for i in 1..20 {
+        dtd.push_str(&format!("<!ENTITY e{} \"&e{};\">", i, i + 1));
+    }
+    dtd.push_str("]>");
+    let data = format!("{}<root>&e1;</root>", dtd);
+
+    let (name, limit) = limit_error(parse(Parser::from_reader(data.as_bytes())));
+    assert_eq!((name.as_str(), limit), ("e17", "nesting depth"));
+
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_limits(options::EntityLimits {
+            max_depth: 20,
+            ..Default::default()
+        })
+        .build();
+    assert_eq!(parse(p).unwrap(), "x");
+}
+
+#[test]
+fn test_entity_limits_custom() {
+    let data = "<!DOCTYPE root [<!ENTITY e \"0123456789\">]><root a='&e;'>&e;&e;</root>";
+    let limits = |max_expanded_size| options::EntityLimits {
+        max_expanded_size,
+        ..Default::default()
+    };
+
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_limits(limits(30))
+        .build();
+    assert_eq!(parse(p).unwrap(), "012345678901234567890123456789");
+
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_limits(limits(29))
+        .build();
+    assert_eq!(limit_error(parse(p)), ("e".to_owned(), "expanded size"));
+}
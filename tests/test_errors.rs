@@ -0,0 +1,188 @@
+mod common;
+
+use common::{first_error, first_error_kind};
+use xml_oxide::sax::{
+    parser::{
+        error::{Error, ErrorKind},
+        Parser,
+    },
+    Event,
+};
+
+#[test]
+fn test_mismatched_end_tag() {
+    assert_eq!(
+        first_error_kind("<root><a></b></root>"),
+        ErrorKind::MismatchedEndTag {
+            expected: "a".to_owned(),
+            found: "b".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("</root>"),
+        ErrorKind::UnmatchedEndTag {
+            name: "root".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_unbound_prefix() {
+    assert_eq!(
+        first_error_kind("<p:root/>"),
+        ErrorKind::UnboundPrefix {
+            prefix: "p".to_owned(),
+            name: "p:root".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root p:a='1'/>"),
+        ErrorKind::UnboundPrefix {
+            prefix: "p".to_owned(),
+            name: "p:a".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_invalid_qname() {
+    assert_eq!(
+        first_error_kind("<a:b:c/>"),
+        ErrorKind::InvalidQName {
+            name: "a:b:c".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_malformed_attribute() {
+    assert_eq!(
+        first_error_kind("<root><a x=1/></root>"),
+        ErrorKind::MalformedAttribute {
+            element: "a".to_owned(),
+            found: "x=1/></root>".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_invalid_character() {
+    assert_eq!(
+        first_error_kind("<root>a\u{1}b</root>"),
+        ErrorKind::InvalidCharacter { character: '\u{1}' }
+    );
+    let mut p = Parser::from_reader(&b"<root>a\xFFb</root>"[..]);
+    let err = loop {
+        match p.read_event() {
+            Ok(Event::EndDocument) => panic!("document should not be well-formed"),
+            Ok(_) => {}
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.kind(), Some(&ErrorKind::InvalidUtf8));
+}
+
+#[test]
+fn test_content_after_root() {
+    assert_eq!(
+        first_error_kind("<root/><second/>"),
+        ErrorKind::ContentAfterRoot {
+            found: "<second/>".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_unclosed_elements() {
+    assert_eq!(
+        first_error_kind("<root><a><b></b>"),
+        ErrorKind::UnclosedElements {
+            names: vec!["root".to_owned(), "a".to_owned()]
+        }
+    );
+    assert_eq!(
+        first_error_kind("<!-- only a comment -->"),
+        ErrorKind::NoRootElement
+    );
+    match first_error(Parser::from_reader("<root><a".as_bytes())) {
+        Error::UnexpectedEof { .. } => {}
+        err => panic!("expected unexpected EOF, found: {:?}", err),
+    }
+}
+
+#[test]
+fn test_duplicate_attribute() {
+    assert_eq!(
+        first_error_kind("<root><a x='1' y='2' x='3'/></root>"),
+        ErrorKind::DuplicateAttribute {
+            element: "a".to_owned(),
+            name: "x".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root xmlns:p='urn:x' xmlns:q='urn:x' p:a='1' q:a='2'/>"),
+        ErrorKind::DuplicateExpandedAttribute {
+            element: "root".to_owned(),
+            first: "p:a".to_owned(),
+            second: "q:a".to_owned(),
+            expanded_name: "{urn:x}a".to_owned()
+        }
+    );
+
+    // same local name in different namespaces, or without a namespace, is fine
+    let mut p = Parser::from_reader(
+        "<root xmlns='urn:x' xmlns:p='urn:x' xmlns:q='urn:y' a='1' p:a='2' q:a='3'/>".as_bytes(),
+    );
+    while p.read_event().unwrap() != Event::EndDocument {}
+}
+
+#[test]
+fn test_reserved_namespaces() {
+    assert_eq!(
+        first_error_kind("<root xmlns:xml='urn:x'/>"),
+        ErrorKind::XmlPrefixRebound {
+            namespace: "urn:x".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root xmlns:xmlns='urn:x'/>"),
+        ErrorKind::XmlnsPrefixDeclared
+    );
+    assert_eq!(
+        first_error_kind("<root xmlns:p='http://www.w3.org/XML/1998/namespace'/>"),
+        ErrorKind::ReservedNamespace {
+            prefix: "p".to_owned(),
+            namespace: "http://www.w3.org/XML/1998/namespace".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root xmlns='http://www.w3.org/2000/xmlns/'/>"),
+        ErrorKind::ReservedNamespace {
+            prefix: "".to_owned(),
+            namespace: "http://www.w3.org/2000/xmlns/".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root xmlns:p=''/>"),
+        ErrorKind::EmptyNamespace {
+            prefix: "p".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<xmlns:root/>"),
+        ErrorKind::XmlnsPrefixedElement {
+            name: "xmlns:root".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_namespace_scope_ends_with_element() {
+    assert_eq!(
+        first_error_kind("<root><a xmlns:p='urn:p'/><p:b/></root>"),
+        ErrorKind::UnboundPrefix {
+            prefix: "p".to_owned(),
+            name: "p:b".to_owned()
+        }
+    );
+}
@@ -0,0 +1,113 @@
+use xml_oxide::sax::{
+    parser::{options, ParserBuilder},
+    OwnedEvent, OwnedReference,
+};
+
+const DOC: &str = r#"<!DOCTYPE doc [
+<!ELEMENT doc (e, p*)>
+<!ELEMENT e EMPTY>
+<!ELEMENT p (#PCDATA | e)*>
+]>
+<doc>
+  <e/>
+  <p> <e/> text </p>
+</doc>"#;
+
+fn content_events(builder: ParserBuilder<&[u8]>) -> Vec<OwnedEvent> {
+    builder
+        .build()
+        .into_owned_events()
+        .map(|event| event.unwrap())
+        .filter(|event| {
+            matches!(
+                event,
+                OwnedEvent::Characters(_)
+                    | OwnedEvent::IgnorableWhitespace(_)
+                    | OwnedEvent::Reference(_)
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_report_ignorable_whitespace() {
+    let expected = vec![
+        OwnedEvent::IgnorableWhitespace("\n  ".to_owned()),
+        OwnedEvent::IgnorableWhitespace("\n  ".to_owned()),
+        OwnedEvent::Characters(" ".to_owned()),
+        OwnedEvent::Characters(" text ".to_owned()),
+        OwnedEvent::IgnorableWhitespace("\n".to_owned()),
+    ];
+    let events = content_events(ParserBuilder::from_reader(DOC.as_bytes()));
+    assert_eq!(events, expected);
+
+    // text that is not merged
+    let events = content_events(
+        ParserBuilder::from_reader(DOC.as_bytes()).references(options::References::Separate),
+    );
+    assert_eq!(events, expected);
+}
+
+#[test]
+fn test_skip_ignorable_whitespace() {
+    let expected = vec![
+        OwnedEvent::Characters(" ".to_owned()),
+        OwnedEvent::Characters(" text ".to_owned()),
+    ];
+    let events = content_events(
+        ParserBuilder::from_reader(DOC.as_bytes())
+            .ignorable_whitespace(options::IgnorableWhitespace::Skip),
+    );
+    assert_eq!(events, expected);
+
+    let events = content_events(
+        ParserBuilder::from_reader(DOC.as_bytes())
+            .ignorable_whitespace(options::IgnorableWhitespace::Skip)
+            .references(options::References::Separate),
+    );
+    assert_eq!(events, expected);
+}
+
+#[test]
+fn test_whitespace_without_declarations() {
+    let doc = "<!DOCTYPE doc [<!ELEMENT e EMPTY>]><doc>\n  <e/>\n</doc>";
+    let events = content_events(
+        ParserBuilder::from_reader(doc.as_bytes())
+            .ignorable_whitespace(options::IgnorableWhitespace::Skip),
+    );
+    assert_eq!(
+        events,
+        vec![
+            OwnedEvent::Characters("\n  ".to_owned()),
+            OwnedEvent::Characters("\n".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_character_reference_not_ignorable() {
+    let doc = "<!DOCTYPE doc [<!ELEMENT doc (e, f?)><!ELEMENT e EMPTY><!ELEMENT f EMPTY>]>\
+        <doc><e/>&#32;<f/></doc>";
+    for skip in [false, true] {
+        let mode = || match skip {
+            false => options::IgnorableWhitespace::Report,
+            true => options::IgnorableWhitespace::Skip,
+        };
+        let events =
+            content_events(ParserBuilder::from_reader(doc.as_bytes()).ignorable_whitespace(mode()));
+        assert_eq!(events, vec![OwnedEvent::Characters(" ".to_owned())]);
+
+        let events = content_events(
+            ParserBuilder::from_reader(doc.as_bytes())
+                .ignorable_whitespace(mode())
+                .references(options::References::Separate),
+        );
+        assert_eq!(
+            events,
+            vec![OwnedEvent::Reference(OwnedReference {
+                raw: "&#32;".to_owned(),
+                resolved: Some(" ".to_owned()),
+            })]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::io::Read;
+
+use common::OneByteReader;
+use xml_oxide::sax::{
+    parser::{options, Parser, ParserBuilder},
+    Event,
+};
+
+fn collect<R: Read>(mut p: Parser<R>) -> (String, Vec<String>) {
+    let mut text = String::new();
+    let mut values = Vec::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::Characters(c) | Event::Cdata(c) | Event::Comment(c) => text.push_str(c),
+            Event::StartElement(el) => {
+                for attr in el.attributes() {
+                    values.push(attr.raw_value.to_owned());
+                }
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    (text, values)
+}
+
+const DATA: &str = "<root a='x\r\ny'>1\r\n2\r3\n\r\n<![CDATA[4\r\n5]]><!--6\r7-->\r\n</root>";
+
+#[test]
+fn test_line_endings_normalized() {
+    let (text, values) = collect(Parser::from_reader(DATA.as_bytes()));
+    assert_eq!(text, "1\n2\n3\n\n4\n56\n7\n");
+    assert_eq!(values, vec!["x\ny"]);
+}
+
+#[test]
+fn test_line_endings_normalized_split_reads() {
+    let (text, values) = collect(Parser::from_reader(OneByteReader(DATA.as_bytes())));
+    assert_eq!(text, "1\n2\n3\n\n4\n56\n7\n");
+    assert_eq!(values, vec!["x\ny"]);
+}
+
+#[test]
+fn test_line_endings_preserved() {
+    let p = ParserBuilder::from_reader(OneByteReader(DATA.as_bytes()))
+        .line_endings(options::LineEndings::Preserve)
+        .build();
+    let (text, values) = collect(p);
+    assert_eq!(text, "1\r\n2\r3\n\r\n4\r\n56\r7\r\n");
+    assert_eq!(values, vec!["x\r\ny"]);
+}
+
+#[test]
+fn test_span_of_split_line_break() {
+    for references in [options::References::Merge, options::References::Separate] {
+        let mut p = ParserBuilder::from_reader(OneByteReader(b"<root>a\r\nb<x/></root>"))
+            .references(references)
+            .build();
+        let mut text = String::new();
+        let mut end = 6;
+        loop {
+            match p.read_event_with_span().unwrap() {
+                (Event::Characters(c), span) => {
+                    // the swallowed `\n` is in the span of the text before it
+                    assert_eq!(span.start.offset, end);
+                    text.push_str(c);
+                    end = span.end.offset;
+                }
+                (Event::StartElement(el), _) if el.name == "x" => break,
+                _ => {}
+            }
+        }
+        assert_eq!(text, "a\nb");
+        assert_eq!(end, 10);
+    }
+}
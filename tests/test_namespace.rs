@@ -60,3 +60,57 @@ fn test_namespaces() {
          xmlns->,xmlns->,xmlns:ns2->,ns2:prefattrb22->urn:b2--2,attrf->,";
     assert_eq!(attribute_namespace_data, expected_attribute_namespace_data);
 }
+
+#[test]
+fn test_xml_prefix_is_bound() {
+    let mut p = Parser::from_reader(
+        "<root xml:lang='en' xmlns:xml='http://www.w3.org/XML/1998/namespace' xmlns=''/>"
+            .as_bytes(),
+    );
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                let lang = el.attributes().next().unwrap();
+                assert_eq!(lang.local_name, "lang");
+                assert_eq!(lang.namespace, "http://www.w3.org/XML/1998/namespace");
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_prefix_mappings() {
+    let data = "<root xmlns='urn:d' xmlns:a='urn:a'><b xmlns:a='urn:a2'/><c><a:d xmlns:e='urn:e'></a:d></c></root>";
+    let mut p = Parser::from_reader(data.as_bytes());
+    let mut scopes = Vec::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::StartElement(el) => {
+                for m in el.prefix_mappings() {
+                    scopes.push(format!("+{} {}={}", el.name, m.prefix, m.namespace));
+                }
+            }
+            Event::EndElement(el) => {
+                for m in el.prefix_mappings() {
+                    scopes.push(format!("-{} {}={}", el.name, m.prefix, m.namespace));
+                }
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(
+        scopes,
+        vec![
+            "+root =urn:d",
+            "+root a=urn:a",
+            "+b a=urn:a2",
+            "+a:d e=urn:e",
+            "-a:d e=urn:e",
+            "-root =urn:d",
+            "-root a=urn:a",
+        ]
+    );
+}
@@ -0,0 +1,90 @@
+use xml_oxide::sax::{
+    parser::Parser, Event, OwnedAttribute, OwnedEndElement, OwnedEvent, OwnedPrefixMapping,
+    OwnedStartElement,
+};
+
+#[test]
+fn test_into_owned_events() {
+    let data = "<p:root xmlns:p='urn:p' p:a='1 &amp; 2'>text</p:root>";
+    let events: Vec<OwnedEvent> = Parser::from_reader(data.as_bytes())
+        .into_owned_events()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert_eq!(
+        events,
+        vec![
+            OwnedEvent::StartDocument,
+            OwnedEvent::StartElement(OwnedStartElement {
+                name: "p:root".to_owned(),
+                attributes: vec![
+                    OwnedAttribute {
+                        value: "urn:p".to_owned(),
+                        raw_value: "urn:p".to_owned(),
+                        name: "xmlns:p".to_owned(),
+                        local_name: "p".to_owned(),
+                        prefix: "xmlns".to_owned(),
+                        namespace: "".to_owned(),
+                        specified: true,
+                    },
+                    OwnedAttribute {
+                        value: "1 & 2".to_owned(),
+                        raw_value: "1 &amp; 2".to_owned(),
+                        name: "p:a".to_owned(),
+                        local_name: "a".to_owned(),
+                        prefix: "p".to_owned(),
+                        namespace: "urn:p".to_owned(),
+                        specified: true,
+                    },
+                ],
+                prefix_mappings: vec![OwnedPrefixMapping {
+                    prefix: "p".to_owned(),
+                    namespace: "urn:p".to_owned(),
+                }],
+                is_empty: false,
+                local_name: "root".to_owned(),
+                prefix: "p".to_owned(),
+                namespace: "urn:p".to_owned(),
+            }),
+            OwnedEvent::Characters("text".to_owned()),
+            OwnedEvent::EndElement(OwnedEndElement {
+                name: "p:root".to_owned(),
+                local_name: "root".to_owned(),
+                prefix: "p".to_owned(),
+                namespace: "urn:p".to_owned(),
+                prefix_mappings: vec![OwnedPrefixMapping {
+                    prefix: "p".to_owned(),
+                    namespace: "urn:p".to_owned(),
+                }],
+            }),
+            OwnedEvent::EndDocument,
+        ]
+    );
+}
+
+#[test]
+fn test_owned_events_stop_after_error() {
+    let mut events = Parser::from_reader("<root></other>".as_bytes()).into_owned_events();
+    assert!(matches!(events.next(), Some(Ok(OwnedEvent::StartDocument))));
+    assert!(matches!(
+        events.next(),
+        Some(Ok(OwnedEvent::StartElement(_)))
+    ));
+    assert!(matches!(events.next(), Some(Err(_))));
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn test_event_to_owned() {
+    let mut p = Parser::from_reader("<root><!--c--></root>".as_bytes());
+    let mut kept = Vec::new();
+    loop {
+        let event = p.read_event().unwrap();
+        kept.push(event.to_owned());
+        if event == Event::EndDocument {
+            break;
+        }
+    }
+    assert_eq!(kept[3], OwnedEvent::Comment("c".to_owned()));
+    assert_eq!(kept.len(), 7);
+}
@@ -0,0 +1,92 @@
+use xml_oxide::{
+    sax::parser::Parser,
+    sax::{Event, Position},
+};
+
+#[test]
+fn test_event_spans() {
+    let data = "<root>\r\n  <a x='1'>kürk</a>\n<b/></root>";
+    let mut p = Parser::from_reader(data.as_bytes());
+
+    let mut spans = Vec::new();
+    loop {
+        let (event, span) = p.read_event_with_span().unwrap();
+        let name = match event {
+            Event::StartElement(el) => el.name.to_owned(),
+            Event::EndElement(el) => format!("/{}", el.name),
+            Event::Characters(c) => format!("{:?}", c),
+            Event::EndDocument => break,
+            _ => continue,
+        };
+        spans.push((name, span));
+    }
+
+    let (name, span) = &spans[2];
+    assert_eq!(name, "a");
+    assert_eq!(
+        span.start,
+        Position {
+            offset: 10,
+            line: 2,
+            column: 3
+        }
+    );
+    assert_eq!(span.end.offset, 10 + "<a x='1'>".len());
+    assert_eq!(&data[span.start.offset..span.end.offset], "<a x='1'>");
+
+    let (name, span) = &spans[4];
+    assert_eq!(name, "/a");
+    assert_eq!(span.start.line, 2);
+    assert_eq!(span.start.column, 16);
+
+    let (name, span) = &spans[6];
+    assert_eq!(name, "b");
+    assert_eq!(
+        span.start,
+        Position {
+            offset: 29,
+            line: 3,
+            column: 1
+        }
+    );
+}
+
+#[test]
+fn test_error_position() {
+    let data = "<root>\n  <a>\n  </b>\n</root>";
+    let mut p = Parser::from_reader(data.as_bytes());
+
+    let err = loop {
+        match p.read_event() {
+            Ok(Event::EndDocument) => panic!("document should not be well-formed"),
+            Ok(_) => {}
+            Err(err) => break err,
+        }
+    };
+
+    assert_eq!(
+        err.position(),
+        Position {
+            offset: 15,
+            line: 3,
+            column: 3
+        }
+    );
+    assert_eq!(p.position(), err.position());
+    assert!(err.to_string().contains("line 3, column 3"));
+}
+
+#[test]
+fn test_eof_position() {
+    let mut p = Parser::from_reader("<root>\n<a".as_bytes());
+
+    let err = loop {
+        match p.read_event() {
+            Ok(Event::EndDocument) => panic!("document should not be well-formed"),
+            Ok(_) => {}
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.position().line, 2);
+    assert_eq!(err.position().offset, 7);
+}
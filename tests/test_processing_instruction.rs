@@ -0,0 +1,85 @@
+mod common;
+
+use common::first_error_kind;
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, Parser},
+    Event, ProcessingInstruction, PseudoAttribute,
+};
+
+#[test]
+fn test_processing_instructions() {
+    let data = "<?xml-stylesheet type=\"text/xsl\" href='style.xsl'?><root><?empty?><?php echo 1; ?></root>";
+    let mut p = Parser::from_reader(data.as_bytes());
+
+    let mut pis = Vec::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::ProcessingInstruction(pi) => {
+                pis.push((pi.target.to_owned(), pi.data.to_owned()));
+                if pi.target == "xml-stylesheet" {
+                    assert_eq!(
+                        pi.pseudo_attributes().collect::<Vec<_>>(),
+                        vec![
+                            PseudoAttribute {
+                                name: "type",
+                                value: "text/xsl"
+                            },
+                            PseudoAttribute {
+                                name: "href",
+                                value: "style.xsl"
+                            }
+                        ]
+                    );
+                }
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(
+        pis,
+        vec![
+            (
+                "xml-stylesheet".to_owned(),
+                "type=\"text/xsl\" href='style.xsl'".to_owned()
+            ),
+            ("empty".to_owned(), "".to_owned()),
+            ("php".to_owned(), "echo 1; ".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_pseudo_attributes_stop_at_malformed_pair() {
+    let pi = ProcessingInstruction {
+        target: "t",
+        data: "a='1' b c='3'",
+    };
+    assert_eq!(
+        pi.pseudo_attributes().collect::<Vec<_>>(),
+        vec![PseudoAttribute {
+            name: "a",
+            value: "1"
+        }]
+    );
+}
+
+#[test]
+fn test_reserved_pi_target() {
+    assert_eq!(
+        first_error_kind("<root><?XmL data?></root>"),
+        ErrorKind::ReservedPiTarget {
+            target: "XmL".to_owned()
+        }
+    );
+    assert_eq!(
+        first_error_kind("<root/>\n<?xml version='1.0'?>"),
+        ErrorKind::ReservedPiTarget {
+            target: "xml".to_owned()
+        }
+    );
+    assert!(matches!(
+        first_error_kind("<root><??></root>"),
+        ErrorKind::Syntax { .. }
+    ));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{content_events, parse};
+use xml_oxide::sax::{
+    parser::{options, Parser, ParserBuilder},
+    Event,
+};
+
+#[test]
+fn test_references_merged() {
+    // undeclared entities are allowed with an external subset
+    let data =
+        "<!DOCTYPE root SYSTEM 'root.dtd'><root>a &amp; b&#60;c&#x3E;&custom;d<x/>&lt;</root>";
+    assert_eq!(
+        parse(data).unwrap(),
+        vec![
+            "<root>",
+            "a & b<c>",
+            "ref &custom;",
+            "d",
+            "<x>",
+            "<",
+            "</root>"
+        ]
+    );
+}
+
+#[test]
+fn test_references_merged_span() {
+    let data = "<root>a &amp; b<x/></root>";
+    let mut p = Parser::from_reader(data.as_bytes());
+    loop {
+        match p.read_event_with_span().unwrap() {
+            (Event::Characters(c), span) => {
+                assert_eq!(c, "a & b");
+                assert_eq!(&data[span.start.offset..span.end.offset], "a &amp; b");
+                break;
+            }
+            (Event::EndDocument, _) => panic!("no characters"),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_references_merged_before_error() {
+    let mut p = Parser::from_reader("<root>a &amp; b & c</root>".as_bytes());
+    while !matches!(p.read_event().unwrap(), Event::StartElement(_)) {}
+    assert_eq!(p.read_event().unwrap(), Event::Characters("a & b "));
+    assert!(p.read_event().is_err());
+}
+
+#[test]
+fn test_references_separate() {
+    let data = "<!DOCTYPE root SYSTEM 'root.dtd'><root>a &amp; b&#60;c&custom;d</root>";
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .references(options::References::Separate)
+        .build();
+    assert_eq!(
+        content_events(p).unwrap(),
+        vec![
+            "<root>",
+            "a ",
+            "ref &amp; &",
+            " b",
+            "ref &#60; <",
+            "c",
+            "ref &custom;",
+            "d",
+            "</root>",
+        ]
+    );
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+
+use common::content_events;
+use xml_oxide::sax::{
+    parser::{
+        error::{Error, ErrorKind},
+        Parser, ParserBuilder,
+    },
+    resolver::{EntityResolver, FileResolver, MemoryResolver},
+};
+
+fn parse(data: &str, resolver: impl EntityResolver + 'static) -> Result<Vec<String>, Error> {
+    let p = ParserBuilder::from_reader(data.as_bytes())
+        .entity_resolver(resolver)
+        .base_uri("docs/doc.xml")
+        .build();
+    content_events(p)
+}
+
+const DOC: &str = r#"<!DOCTYPE doc SYSTEM "dtd/doc.dtd" [
+<!ENTITY chapter SYSTEM "chapter.xml">
+]>
+<doc>&chapter;&note;</doc>"#;
+
+const DTD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!ENTITY note SYSTEM "note.xml">
+<!ENTITY chapter "ignored, the internal subset binds">
+<!ATTLIST doc version CDATA "2">"#;
+
+fn memory_resolver() -> MemoryResolver {
+    let mut resolver = MemoryResolver::new();
+    resolver
+        .insert("docs/dtd/doc.dtd", DTD)
+        .insert("docs/chapter.xml", "<?xml encoding='UTF-8'?><p>chapter</p>")
+        .insert("docs/dtd/note.xml", "note");
+    resolver
+}
+
+#[test]
+fn test_resolver_denies_by_default() {
+    let p = Parser::from_reader(DOC.as_bytes());
+    assert_eq!(
+        content_events(p).unwrap(),
+        vec!["<doc>", "ref &chapter;", "ref &note;", "</doc>"]
+    );
+}
+
+#[test]
+fn test_memory_resolver() {
+    assert_eq!(
+        parse(DOC, memory_resolver()).unwrap(),
+        vec![
+            "<doc version='2'>",
+            "<p>",
+            "chapter",
+            "</p>",
+            "note",
+            "</doc>"
+        ]
+    );
+
+    let data = "<!DOCTYPE doc [<!ENTITY e PUBLIC '-//Example//E' 'missing.xml'>]><doc>&e;</doc>";
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("-//Example//E", "by public id");
+    assert_eq!(
+        parse(data, resolver).unwrap(),
+        vec!["<doc>", "by public id", "</doc>"]
+    );
+
+    assert_eq!(
+        parse(data, MemoryResolver::new()).unwrap_err().kind(),
+        Some(&ErrorKind::ExternalEntity {
+            system_id: "missing.xml".to_owned(),
+            reason: "unknown entity: missing.xml".to_owned(),
+        })
+    );
+}
+
+#[test]
+fn test_external_entity_in_attribute() {
+    let data = "<!DOCTYPE doc [<!ENTITY e SYSTEM 'e.xml'>]><doc a='&e;'/>";
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("docs/e.xml", "text");
+    assert_eq!(
+        parse(data, resolver).unwrap_err().kind(),
+        Some(&ErrorKind::ExternalEntityInAttribute {
+            name: "e".to_owned()
+        })
+    );
+}
+
+#[test]
+fn test_file_resolver() {
+    let root = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("resolver_root");
+    fs::create_dir_all(root.join("docs/dtd")).unwrap();
+    fs::write(root.join("docs/dtd/doc.dtd"), DTD).unwrap();
+    fs::write(root.join("docs/chapter.xml"), "<p>chapter</p>").unwrap();
+    fs::write(root.join("docs/dtd/note.xml"), "note").unwrap();
+    fs::write(root.join("../outside.xml"), "secret").unwrap();
+
+    assert_eq!(
+        parse(DOC, FileResolver::new(&root)).unwrap(),
+        vec![
+            "<doc version='2'>",
+            "<p>",
+            "chapter",
+            "</p>",
+            "note",
+            "</doc>"
+        ]
+    );
+
+    for system_id in ["../../outside.xml", "http://example.com/e.xml"] {
+        let data = format!(
+            "<!DOCTYPE doc [<!ENTITY e SYSTEM '{}'>]><doc>&e;</doc>",
+            system_id
+        );
+        match parse(&data, FileResolver::new(&root)) {
+            Err(Error::Parsing {
+                kind: ErrorKind::ExternalEntity { reason, .. },
+                ..
+            }) => {
+                assert!(
+                    reason.starts_with("outside of the resolver root"),
+                    "{}",
+                    reason
+                )
+            }
+            other => panic!("{} is resolved: {:?}", system_id, other),
+        }
+    }
+}
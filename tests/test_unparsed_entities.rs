@@ -0,0 +1,116 @@
+use xml_oxide::sax::{
+    declaration::{NotationDecl, UnparsedEntity},
+    parser::ParserBuilder,
+    resolver::MemoryResolver,
+    Event,
+};
+
+const DOC: &str = r#"<!DOCTYPE gallery SYSTEM "gallery.dtd" [
+<!NOTATION gif PUBLIC "-//CompuServe//NOTATION Graphics Interchange Format//EN">
+<!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+<!ENTITY title "Gallery">
+<!ENTITY logo SYSTEM "other.gif" NDATA gif>
+<!ATTLIST image
+    src ENTITY #REQUIRED
+    alt CDATA #IMPLIED>
+<!ATTLIST gallery images ENTITIES #IMPLIED>
+]>
+<gallery images="logo photo title missing"><image src="logo" alt="logo"/></gallery>"#;
+
+const GALLERY_DTD: &str = r#"<!NOTATION jpeg SYSTEM "image/jpeg">
+<!ENTITY photo PUBLIC "-//Example//Photo//EN" "photo.jpg" NDATA jpeg>
+<!ENTITY scan SYSTEM "scan.tiff" NDATA tiff>"#;
+
+#[test]
+fn test_unparsed_entity_table() {
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("gallery.dtd", GALLERY_DTD);
+    let mut p = ParserBuilder::from_reader(DOC.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    loop {
+        if let Event::DocumentTypeDeclaration(_) = p.read_event().unwrap() {
+            break;
+        }
+    }
+
+    let dtd = p.dtd().unwrap();
+    let gif = NotationDecl {
+        name: "gif".to_owned(),
+        public_id: Some("-//CompuServe//NOTATION Graphics Interchange Format//EN".to_owned()),
+        system_id: None,
+    };
+    let jpeg = NotationDecl {
+        name: "jpeg".to_owned(),
+        public_id: None,
+        system_id: Some("image/jpeg".to_owned()),
+    };
+    assert_eq!(dtd.notations(), vec![&gif, &jpeg]);
+
+    let logo = UnparsedEntity {
+        name: "logo",
+        public_id: None,
+        system_id: "logo.gif",
+        notation: "gif",
+        notation_decl: Some(&gif),
+    };
+    let photo = UnparsedEntity {
+        name: "photo",
+        public_id: Some("-//Example//Photo//EN"),
+        system_id: "photo.jpg",
+        notation: "jpeg",
+        notation_decl: Some(&jpeg),
+    };
+    let scan = UnparsedEntity {
+        name: "scan",
+        public_id: None,
+        system_id: "scan.tiff",
+        notation: "tiff",
+        notation_decl: None,
+    };
+    assert_eq!(dtd.unparsed_entities(), vec![logo, photo, scan]);
+    assert_eq!(dtd.unparsed_entity("logo"), Some(logo));
+    assert_eq!(dtd.unparsed_entity("title"), None);
+}
+
+#[test]
+fn test_entity_attributes() {
+    let mut resolver = MemoryResolver::new();
+    resolver.insert("gallery.dtd", GALLERY_DTD);
+    let mut p = ParserBuilder::from_reader(DOC.as_bytes())
+        .entity_resolver(resolver)
+        .build();
+    // events borrow the parser, the DTD is copied to use it with them
+    let mut dtd = None;
+    let mut resolved = Vec::new();
+    loop {
+        match p.read_event().unwrap() {
+            Event::DocumentTypeDeclaration(_) => dtd = p.dtd().cloned(),
+            Event::StartElement(el) => {
+                let dtd = dtd.as_ref().unwrap();
+                for attribute in el.attributes() {
+                    let entities = dtd.attribute_entities(el.name, &attribute).map(|entities| {
+                        entities
+                            .iter()
+                            .map(|entity| entity.system_id.to_owned())
+                            .collect::<Vec<_>>()
+                    });
+                    resolved.push((attribute.name.to_owned(), entities));
+                }
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+    assert_eq!(
+        resolved,
+        vec![
+            (
+                "images".to_owned(),
+                Some(vec!["logo.gif".to_owned(), "photo.jpg".to_owned()])
+            ),
+            ("src".to_owned(), Some(vec!["logo.gif".to_owned()])),
+            ("alt".to_owned(), None),
+        ]
+    );
+}
@@ -0,0 +1,291 @@
+use xml_oxide::sax::{
+    parser::{options, ParserBuilder},
+    resolver::MemoryResolver,
+    validation::ValidityErrorKind,
+    Event,
+};
+
+const DTD: &str = r#"<!DOCTYPE library [
+<!ELEMENT library (shelf+, note?)>
+<!ELEMENT shelf (book | magazine)*>
+<!ELEMENT book (title, author+)>
+<!ELEMENT magazine EMPTY>
+<!ELEMENT title (#PCDATA)>
+<!ELEMENT author (#PCDATA)>
+<!ELEMENT note (#PCDATA | em)*>
+<!ELEMENT em (#PCDATA)>
+<!ATTLIST shelf id ID #REQUIRED>
+<!ATTLIST book
+    id ID #IMPLIED
+    shelf IDREF #IMPLIED
+    related IDREFS #IMPLIED
+    lang NMTOKEN "en"
+    format (paper | ebook) "paper"
+    cover ENTITY #IMPLIED>
+<!ATTLIST magazine issues NMTOKENS #IMPLIED>
+<!NOTATION png SYSTEM "image/png">
+<!ENTITY front SYSTEM "front.png" NDATA png>
+<!ENTITY aside "<em>aside</em>">
+]>
+"#;
+
+fn validate(body: &str) -> Vec<ValidityErrorKind> {
+    let doc = format!("{}{}", DTD, body);
+    let mut p = ParserBuilder::from_reader(doc.as_bytes())
+        .validation(options::Validation::Enabled)
+        .build();
+    loop {
+        if p.read_event().unwrap() == Event::EndDocument {
+            break;
+        }
+    }
+    p.validity_errors().iter().map(|e| e.kind.clone()).collect()
+}
+
+#[test]
+fn test_valid_document() {
+    let errors = validate(
+        r#"<library>
+  <shelf id="s1">
+    <book id="b1" shelf="s1" related="b2 b1" cover="front">
+      <title>Dune</title><author>Frank Herbert</author>
+    </book>
+    <book id="b2" format="ebook"><title>Emma</title><author>A</author><author>B</author></book>
+    <magazine issues="1 2 3"/>
+  </shelf>
+  <shelf id="s2"/>
+  <note>Text &aside; <!-- comment --> <![CDATA[<raw>]]></note>
+</library>"#,
+    );
+    assert_eq!(errors, vec![]);
+}
+
+#[test]
+fn test_content_models() {
+    let errors = validate(
+        r#"<library>
+  <shelf id="s1">
+    <book><author>A</author></book>
+    <book><title>T</title></book>
+    <magazine> </magazine>
+    text
+  </shelf>
+  <note><title>T</title></note>
+  <shelf id="s2"/>
+</library>"#,
+    );
+    assert_eq!(
+        errors,
+        vec![
+            ValidityErrorKind::UnexpectedContent {
+                element: "book".to_owned(),
+                found: "element author".to_owned(),
+            },
+            ValidityErrorKind::IncompleteContent {
+                element: "book".to_owned(),
+            },
+            ValidityErrorKind::UnexpectedContent {
+                element: "magazine".to_owned(),
+                found: "text".to_owned(),
+            },
+            ValidityErrorKind::UnexpectedContent {
+                element: "shelf".to_owned(),
+                found: "text".to_owned(),
+            },
+            ValidityErrorKind::UnexpectedContent {
+                element: "note".to_owned(),
+                found: "element title".to_owned(),
+            },
+            ValidityErrorKind::UnexpectedContent {
+                element: "library".to_owned(),
+                found: "element shelf".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_undeclared_elements() {
+    let errors = validate(r#"<library><shelf id="s"/><box/></library>"#);
+    assert_eq!(
+        errors,
+        vec![
+            ValidityErrorKind::UnexpectedContent {
+                element: "library".to_owned(),
+                found: "element box".to_owned(),
+            },
+            ValidityErrorKind::UndeclaredElement {
+                name: "box".to_owned(),
+            },
+        ]
+    );
+
+    let errors = validate(r#"<shelf id="s"/>"#);
+    assert_eq!(
+        errors,
+        vec![ValidityErrorKind::RootElement {
+            expected: "library".to_owned(),
+            found: "shelf".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn test_attributes() {
+    let errors = validate(
+        r#"<library>
+  <shelf>
+    <book id="1b" lang="a b" format="audio" cover="aside" size="big">
+      <title>T</title><author>A</author>
+    </book>
+  </shelf>
+</library>"#,
+    );
+    let invalid = |name: &str, value: &str| ValidityErrorKind::InvalidAttributeValue {
+        element: "book".to_owned(),
+        name: name.to_owned(),
+        value: value.to_owned(),
+    };
+    assert_eq!(
+        errors,
+        vec![
+            ValidityErrorKind::MissingAttribute {
+                element: "shelf".to_owned(),
+                name: "id".to_owned(),
+            },
+            invalid("id", "1b"),
+            invalid("lang", "a b"),
+            invalid("format", "audio"),
+            invalid("cover", "aside"),
+            ValidityErrorKind::UndeclaredAttribute {
+                element: "book".to_owned(),
+                name: "size".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_ids() {
+    let errors = validate(
+        r#"<library>
+  <shelf id="s1"><book shelf="s2" related="s1 s3"><title/><author/></book></shelf>
+  <shelf id="s1"/>
+</library>"#,
+    );
+    assert_eq!(
+        errors,
+        vec![
+            ValidityErrorKind::DuplicateId {
+                value: "s1".to_owned(),
+            },
+            ValidityErrorKind::UndefinedIdRef {
+                value: "s2".to_owned(),
+            },
+            ValidityErrorKind::UndefinedIdRef {
+                value: "s3".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_external_subset() {
+    let doc = r#"<!DOCTYPE list SYSTEM "list.dtd"><list><item/><item/></list>"#;
+    let mut resolver = MemoryResolver::new();
+    resolver.insert(
+        "list.dtd",
+        "<!ELEMENT list (item, item?)><!ELEMENT item EMPTY>",
+    );
+    let mut p = ParserBuilder::from_reader(doc.as_bytes())
+        .entity_resolver(resolver)
+        .validation(options::Validation::Enabled)
+        .build();
+    loop {
+        if p.read_event().unwrap() == Event::EndDocument {
+            break;
+        }
+    }
+    assert!(p.validity_errors().is_empty());
+}
+
+#[test]
+fn test_validity_errors_are_separate() {
+    // without a DOCTYPE the document is well-formed but not valid
+    let doc = "<root><a/></root>";
+    let mut p = ParserBuilder::from_reader(doc.as_bytes())
+        .validation(options::Validation::Enabled)
+        .build();
+    let mut events = 0;
+    loop {
+        events += 1;
+        if p.read_event().unwrap() == Event::EndDocument {
+            break;
+        }
+    }
+    assert_eq!(events, 5);
+    let errors = p.validity_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ValidityErrorKind::MissingDoctype);
+    assert_eq!(errors[0].position.offset, 0);
+
+    // validation is opt-in
+    let mut p = ParserBuilder::from_reader(doc.as_bytes()).build();
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert!(p.validity_errors().is_empty());
+}
+
+#[test]
+fn test_coalesced_cdata() {
+    let doc = format!(
+        "{}{}",
+        DTD,
+        r#"<library><shelf id="s1"><![CDATA[ ]]></shelf><note><![CDATA[<b>]]></note></library>"#
+    );
+    let expected = vec![ValidityErrorKind::UnexpectedContent {
+        element: "shelf".to_owned(),
+        found: "CDATA section".to_owned(),
+    }];
+    for include_cdata in [false, true] {
+        let mut p = ParserBuilder::from_reader(doc.as_bytes())
+            .validation(options::Validation::Enabled)
+            .text_coalescing(options::TextCoalescing::Enabled {
+                include_cdata,
+                max_size: 1024,
+            })
+            .build();
+        loop {
+            match p.read_event().unwrap() {
+                // whitespace in a CDATA section is not ignorable
+                Event::IgnorableWhitespace(_) => panic!("ignorable CDATA section"),
+                Event::EndDocument => break,
+                _ => {}
+            }
+        }
+        let errors: Vec<_> = p.validity_errors().iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(errors, expected);
+    }
+}
+
+#[test]
+fn test_character_reference_in_element_content() {
+    let doc = "<!DOCTYPE doc [<!ELEMENT doc (e)><!ELEMENT e EMPTY>]><doc>&#32;<e/></doc>";
+    for (references, found) in [
+        (options::References::Merge, "text"),
+        (options::References::Separate, "reference &#32;"),
+    ] {
+        let mut p = ParserBuilder::from_reader(doc.as_bytes())
+            .validation(options::Validation::Enabled)
+            .references(references)
+            .build();
+        while p.read_event().unwrap() != Event::EndDocument {}
+        let errors: Vec<_> = p.validity_errors().iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            errors,
+            vec![ValidityErrorKind::UnexpectedContent {
+                element: "doc".to_owned(),
+                found: found.to_owned(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::first_error_kind;
+use xml_oxide::sax::{
+    parser::{error::ErrorKind, Parser},
+    Event, XmlDecl,
+};
+
+#[test]
+fn test_xml_declaration() {
+    let mut p = Parser::from_reader(
+        "<?xml version='1.0' encoding='utf-8' standalone=\"no\"?><root/>".as_bytes(),
+    );
+    assert_eq!(p.xml_declaration(), None);
+
+    loop {
+        match p.read_event().unwrap() {
+            Event::XmlDeclaration(decl) => {
+                assert_eq!(
+                    decl,
+                    XmlDecl {
+                        version: "1.0",
+                        encoding: Some("utf-8"),
+                        standalone: Some(false)
+                    }
+                );
+            }
+            Event::EndDocument => break,
+            _ => {}
+        }
+    }
+
+    assert_eq!(
+        p.xml_declaration(),
+        Some(XmlDecl {
+            version: "1.0",
+            encoding: Some("utf-8"),
+            standalone: Some(false)
+        })
+    );
+}
+
+#[test]
+fn test_xml_declaration_minimal() {
+    let mut p = Parser::from_reader("<?xml version=\"1.0\"?><root/>".as_bytes());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert_eq!(
+        p.xml_declaration(),
+        Some(XmlDecl {
+            version: "1.0",
+            encoding: None,
+            standalone: None
+        })
+    );
+
+    let mut p = Parser::from_reader("<root/>".as_bytes());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert_eq!(p.xml_declaration(), None);
+}
+
+#[test]
+fn test_later_xml_version() {
+    // processed as 1.0, the version is reported as it is
+    let mut p = Parser::from_reader("<?xml version='1.1'?><root/>".as_bytes());
+    while p.read_event().unwrap() != Event::EndDocument {}
+    assert_eq!(p.xml_declaration().unwrap().version, "1.1");
+
+    assert_eq!(
+        first_error_kind("<?xml version='2.0'?><root/>"),
+        ErrorKind::UnsupportedVersion {
+            version: "2.0".to_owned()
+        }
+    );
+}
+
+#[test]
+fn test_unsupported_xml_declaration() {
+    assert_eq!(
+        first_error_kind("<?xml version='1.0' encoding='EUC-JP'?><root/>"),
+        ErrorKind::UnsupportedEncoding {
+            encoding: "EUC-JP".to_owned()
+        }
+    );
+}